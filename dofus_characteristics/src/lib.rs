@@ -47,12 +47,22 @@ pub trait Restriction: Debug {
     fn accepts(&self, characteristics: &Characteristic, set_bonus: i32) -> i32;
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum BooleanOperator {
     And,
     Or,
 }
 
+impl BooleanOperator {
+    /// Combines the violations of each child restriction into the violation of the whole
+    pub fn combine(self, violations: impl Iterator<Item = i32>) -> i32 {
+        match self {
+            BooleanOperator::And => violations.sum(),
+            BooleanOperator::Or => violations.max().unwrap_or(0),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct RestrictionSet {
     pub operator: BooleanOperator,
@@ -61,29 +71,21 @@ pub struct RestrictionSet {
 
 impl Restriction for RestrictionSet {
     fn accepts(&self, characteristics: &Characteristic, set_bonus: i32) -> i32 {
-        match self.operator {
-            BooleanOperator::And => self
-                .restrictions
-                .iter()
-                .map(|restriction| restriction.accepts(characteristics, set_bonus))
-                .sum(),
-            BooleanOperator::Or => self
-                .restrictions
+        self.operator.combine(
+            self.restrictions
                 .iter()
-                .map(|restriction| restriction.accepts(characteristics, set_bonus))
-                .max()
-                .unwrap(),
-        }
+                .map(|restriction| restriction.accepts(characteristics, set_bonus)),
+        )
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Operator {
     GreaterThan,
     LessThan,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct RestrictionLeaf {
     pub operator: Operator,
     pub stat: Stat,
@@ -105,7 +107,7 @@ impl Restriction for RestrictionLeaf {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct SetBonusRestriction {
     pub operator: Operator,
     pub value: i32,
@@ -121,7 +123,7 @@ impl Restriction for SetBonusRestriction {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct NullRestriction;
 
 impl Restriction for NullRestriction {
@@ -139,6 +141,10 @@ impl Characteristic {
         self.0.iter()
     }
 
+    pub fn iter_mut(&mut self) -> core::slice::IterMut<'_, i32> {
+        self.0.iter_mut()
+    }
+
     pub const fn new_from_raw(raw: [i32; 51]) -> Self {
         Self(raw)
     }
@@ -328,6 +334,67 @@ pub const STAT_ELEMENT: [Stat; 4] = [
     Stat::Intelligence,
 ];
 
+/// The elements damage can be dealt in, in the order DofusLab lists spell damage
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize)]
+pub enum Element {
+    Neutral,
+    Air,
+    Water,
+    Earth,
+    Fire,
+}
+
+impl Element {
+    pub const ALL: [Element; 5] = [
+        Element::Neutral,
+        Element::Air,
+        Element::Water,
+        Element::Earth,
+        Element::Fire,
+    ];
+
+    /// The characteristic that increases damage in this element. Neutral damage is scaled by
+    /// strength like earth damage.
+    pub const fn characteristic(self) -> Stat {
+        match self {
+            Element::Neutral | Element::Earth => Stat::Strength,
+            Element::Air => Stat::Agility,
+            Element::Water => Stat::Chance,
+            Element::Fire => Stat::Intelligence,
+        }
+    }
+
+    pub const fn damage(self) -> Stat {
+        match self {
+            Element::Neutral => Stat::DamageNeutral,
+            Element::Air => Stat::DamageAir,
+            Element::Water => Stat::DamageWater,
+            Element::Earth => Stat::DamageEarth,
+            Element::Fire => Stat::DamageFire,
+        }
+    }
+
+    pub const fn resistance_fixed(self) -> Stat {
+        match self {
+            Element::Neutral => Stat::ResistanceNeutralFixed,
+            Element::Air => Stat::ResistanceAirFixed,
+            Element::Water => Stat::ResistanceWaterFixed,
+            Element::Earth => Stat::ResistanceEarthFixed,
+            Element::Fire => Stat::ResistanceFireFixed,
+        }
+    }
+
+    pub const fn resistance_percent(self) -> Stat {
+        match self {
+            Element::Neutral => Stat::ResistanceNeutralPercent,
+            Element::Air => Stat::ResistanceAirPercent,
+            Element::Water => Stat::ResistanceWaterPercent,
+            Element::Earth => Stat::ResistanceEarthPercent,
+            Element::Fire => Stat::ResistanceFirePercent,
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -355,4 +422,16 @@ mod tests {
         assert_eq!(set_restriction.accepts(&Characteristic([0; 51]), 2), 0);
         assert_eq!(set_restriction.accepts(&Characteristic([0; 51]), 3), 100);
     }
+
+    #[test]
+    fn element_stats() {
+        assert_eq!(Element::Neutral.characteristic(), Stat::Strength);
+        assert_eq!(Element::Earth.characteristic(), Stat::Strength);
+        assert_eq!(Element::Air.characteristic(), Stat::Agility);
+        assert_eq!(
+            Element::Water.resistance_percent(),
+            Stat::ResistanceWaterPercent
+        );
+        assert_eq!(Element::Fire.damage(), Stat::DamageFire);
+    }
 }
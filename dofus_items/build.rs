@@ -1,22 +1,18 @@
 use std::{error::Error, fs::File, io::BufWriter};
 
-use dofus_characteristics::{Characteristic, Stat, StatConversionError};
+use dofus_characteristics::{BooleanOperator, Operator};
 use proc_macro2::TokenStream;
 use quote::{format_ident, quote, ToTokens};
 use serde::Deserialize;
-use serde_json::Value;
-use std::{collections::HashMap, convert::TryInto, io::Write};
+use std::io::Write;
 
-#[derive(Deserialize, Debug)]
-struct DofusLabConditions {
-    conditions: serde_json::Map<String, serde_json::value::Value>,
-}
+#[path = "src/dofus_lab.rs"]
+mod dofus_lab;
 
-#[allow(non_snake_case)]
-#[derive(Deserialize, Debug)]
-struct DofusLabLocalised {
-    en: String,
-}
+use dofus_lab::{
+    Damage, DamageLine, DofusLabEffectElement, DofusLabLocalised, ElementDamage, ParsedItem,
+    ParsedSet, RestrictionTree, StringI32, Weapon,
+};
 
 impl ToTokens for DofusLabLocalised {
     fn to_tokens(&self, tokens: &mut TokenStream) {
@@ -24,73 +20,18 @@ impl ToTokens for DofusLabLocalised {
     }
 }
 
-#[allow(non_snake_case)]
-#[derive(Deserialize, Debug)]
-struct DofusLabItemStats {
-    stat: String,
-    maxStat: i32,
-}
-
-#[allow(non_snake_case)]
-#[derive(Deserialize, Debug)]
-struct DofusLabItem {
-    name: DofusLabLocalised,
-    itemType: String,
-    setID: Option<String>,
-    stats: Option<Vec<DofusLabItemStats>>,
-    level: i32,
-    conditions: Option<DofusLabConditions>,
-    imageUrl: Option<String>,
-}
-
-#[derive(Deserialize)]
-struct DofusLabStatRestriction {
-    stat: String,
-    operator: String,
-    value: i32,
-}
-
 #[derive(Deserialize)]
 struct DofusLabSpellClass {
     names: DofusLabLocalised,
     spells: Vec<Vec<DofusLabSpell>>,
 }
 
-#[derive(Deserialize)]
-#[serde(rename_all = "camelCase")]
-struct DofusLabEffectElement {
-    stat: String,
-    min_stat: Option<i32>,
-    max_stat: StringI32,
-}
-
 #[derive(Deserialize)]
 #[serde(rename_all = "camelCase")]
 struct DofusLabEffect {
     modifiable_effect: Option<Vec<DofusLabEffectElement>>,
 }
 
-#[derive(Clone, Copy)]
-struct StringI32(i32);
-
-impl<'de> Deserialize<'de> for StringI32 {
-    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
-    where
-        D: serde::Deserializer<'de>,
-    {
-        Ok(match Value::deserialize(deserializer)? {
-            Value::String(s) => s.parse().map(StringI32).map_err(serde::de::Error::custom)?,
-            Value::Number(n) => n
-                .as_i64()
-                .ok_or(serde::de::Error::custom("wrong type"))?
-                .try_into()
-                .map(StringI32)
-                .map_err(serde::de::Error::custom)?,
-            _ => return Err(serde::de::Error::custom("wrong type")),
-        })
-    }
-}
-
 #[derive(Deserialize)]
 #[serde(rename_all = "camelCase")]
 struct DofusLabSpellEffect {
@@ -109,302 +50,202 @@ struct DofusLabSpell {
     effects: Vec<DofusLabSpellEffect>,
 }
 
-fn parse_restriction(value: &serde_json::Map<String, serde_json::value::Value>) -> TokenStream {
-    if value.is_empty() {
-        return quote! { &NullRestriction {}};
+fn quote_operator(operator: Operator) -> TokenStream {
+    match operator {
+        Operator::LessThan => quote! { Operator::LessThan },
+        Operator::GreaterThan => quote! { Operator::GreaterThan },
     }
+}
 
-    if let Some(and_restriction) = value.get("and") {
-        let and_restriction = and_restriction.as_array().unwrap();
-        let and_restriction = and_restriction
-            .iter()
-            .map(|r| parse_restriction(r.as_object().unwrap()));
-        quote! { & RestrictionSet {
-            operator: BooleanOperator::And,
-            restrictions: &[#(#and_restriction),*],
-        } }
-    } else if let Some(or_restriction) = value.get("or") {
-        let or_restriction = or_restriction.as_array().unwrap();
-        let or_restriction = or_restriction
-            .iter()
-            .map(|r| parse_restriction(r.as_object().unwrap()));
-        quote! { &RestrictionSet {
-            operator: BooleanOperator::Or,
-            restrictions: &[#(#or_restriction),*],
-        } }
-    } else {
-        let stat: DofusLabStatRestriction =
-            serde_json::from_value(serde_json::Value::Object(value.clone())).unwrap();
-        let operator = match stat.operator.as_str() {
-            "<" => quote! { Operator::LessThan },
-            ">" => quote! { Operator::GreaterThan },
-            _ => panic!("Bad operator"),
-        };
-
-        let value = stat.value;
-
-        if stat.stat == "SET_BONUS" {
-            quote! { &SetBonusRestriction {
+fn quote_restriction(restriction: &RestrictionTree) -> TokenStream {
+    match restriction {
+        RestrictionTree::Null => quote! { &NullRestriction {} },
+        RestrictionTree::Leaf(leaf) => {
+            let value = leaf.value;
+            let operator = quote_operator(leaf.operator);
+            let stat = format_ident!("{}", format!("{:?}", leaf.stat));
+
+            quote! { &RestrictionLeaf {
                 value: #value,
                 operator: #operator,
+                stat: Stat::#stat,
             }}
-        } else {
-            match Stat::try_from(stat.stat.as_str()) {
-                Ok(s) => {
-                    let stat = s;
-
-                    let stat = format_ident!("{}", stat.to_string());
-
-                    quote! { &RestrictionLeaf {
-                        value: #value,
-                        operator: #operator,
-                        stat: Stat::#stat,
-                    }}
-                }
-                Err(StatConversionError::IntentionallyIgnored) => quote! { &NullRestriction {}},
-                _ => panic!("Unsupported stat {}", stat.stat),
-            }
         }
-    }
-}
+        RestrictionTree::SetBonus(set_bonus) => {
+            let value = set_bonus.value;
+            let operator = quote_operator(set_bonus.operator);
 
-fn parse_items(
-    data: &[&[u8]],
-    set_mappings: &HashMap<String, usize>,
-) -> Vec<(TokenStream, String)> {
-    let data: Vec<DofusLabItem> = data
-        .iter()
-        .flat_map(|data| serde_json::from_slice::<Vec<DofusLabItem>>(data).unwrap())
-        .collect();
-
-    data.iter()
-        .map(|item| {
-            let mut stats = Characteristic::new();
-            if let Some(item_stats) = item.stats.as_ref() {
-                for stat in item_stats {
-                    let characteristic: Stat = stat.stat.as_str().try_into().unwrap();
-                    stats[characteristic] = stat.maxStat;
-                }
-            }
-
-            let restriction = item
-                .conditions
-                .as_ref()
-                .map(|conditions| parse_restriction(&conditions.conditions))
-                .unwrap_or_else(|| quote! {& NullRestriction {}});
-
-            let name = &item.name;
-            let item_type = &item.itemType;
-            let level = item.level;
-            let set_id = item
-                .setID
-                .as_ref()
-                .and_then(|id| set_mappings.get(id).copied());
-            let set_id = match set_id {
-                Some(x) => quote! { Some(SetIndex(#x))},
-                None => quote! {None},
+            quote! { &SetBonusRestriction {
+                value: #value,
+                operator: #operator,
+            }}
+        }
+        RestrictionTree::Set {
+            operator,
+            restrictions,
+        } => {
+            let operator = match operator {
+                BooleanOperator::And => quote! { BooleanOperator::And },
+                BooleanOperator::Or => quote! { BooleanOperator::Or },
             };
-            let image_url = item.imageUrl.as_ref().unwrap();
-
-            (
-                quote! { Item {
-                    name: #name,
-                    item_type: #item_type,
-                    stats: #stats,
-                    level: #level,
-                    set_id: #set_id,
-                    restriction: #restriction,
-                    image_url: #image_url,
-                } },
-                item_type.clone(),
-            )
-        })
-        .collect()
-}
-
-#[derive(Debug)]
-pub struct Set {
-    pub name: String,
-    pub bonus_start_at: usize,
-    pub bonuses: Vec<Characteristic>,
-}
+            let restrictions = restrictions.iter().map(quote_restriction);
 
-#[derive(Debug, Deserialize)]
-struct DofusLabSetStat {
-    stat: Option<String>,
-    value: Option<i32>,
+            quote! { &RestrictionSet {
+                operator: #operator,
+                restrictions: &[#(#restrictions),*],
+            } }
+        }
+    }
 }
 
-#[derive(Debug, Deserialize)]
-struct DofusLabSet {
-    name: DofusLabLocalised,
-    id: String,
-    bonuses: HashMap<String, Vec<DofusLabSetStat>>,
+fn quote_item(item: &ParsedItem) -> TokenStream {
+    let name = &item.name;
+    let item_type = &item.item_type;
+    let stats = &item.stats;
+    let level = item.level;
+    let set_id = match item.set_id {
+        Some(x) => quote! { Some(SetIndex(#x))},
+        None => quote! {None},
+    };
+    let restriction = quote_restriction(&item.restriction);
+    let image_url = &item.image_url;
+    let weapon = quote_option(item.weapon.as_ref());
+
+    quote! { Item {
+        name: Cow::Borrowed(#name),
+        item_type: Cow::Borrowed(#item_type),
+        stats: #stats,
+        level: #level,
+        set_id: #set_id,
+        restriction: ItemRestriction::Compiled(#restriction),
+        image_url: Cow::Borrowed(#image_url),
+        weapon: #weapon,
+    } }
 }
 
-fn parse_sets(data: &[u8]) -> (HashMap<String, usize>, Vec<Set>) {
-    let data: Vec<DofusLabSet> = serde_json::from_slice(data).unwrap();
-
-    let mut dofus_id_to_internal_id_mapping = HashMap::default();
-
-    let sets: Vec<Set> = data
-        .iter()
-        .enumerate()
-        .map(|(idx, set)| {
-            let item_count_to_bonus: Vec<(usize, _)> = set
-                .bonuses
-                .iter()
-                .map(|(number_of_items, bonus)| {
-                    let mut stats = Characteristic::new();
-                    for stat in bonus {
-                        if let (Some(stat), Some(value)) = (&stat.stat, stat.value) {
-                            let characteristic: Stat = stat.as_str().try_into().unwrap();
-                            stats[characteristic] = value;
-                        }
-                    }
-
-                    (number_of_items.parse().unwrap(), stats)
-                })
-                .collect();
+fn quote_set(set: &ParsedSet) -> TokenStream {
+    let name = &set.name;
+    let bonuses = &set.bonuses;
+    let start_at = set.bonus_start_at;
 
-            let minimum_number_of_items = item_count_to_bonus.iter().map(|x| x.0).min().unwrap();
-
-            let maximum_number_of_items = item_count_to_bonus.iter().map(|x| x.0).max().unwrap();
-
-            let mut bonuses =
-                vec![Characteristic::new(); maximum_number_of_items - minimum_number_of_items + 1];
-            for (idx, bonus) in item_count_to_bonus.into_iter() {
-                bonuses[idx - minimum_number_of_items] = bonus;
-            }
-
-            dofus_id_to_internal_id_mapping.insert(set.id.clone(), idx);
-
-            Set {
-                name: set.name.en.to_owned(),
-                bonus_start_at: minimum_number_of_items,
-                bonuses,
-            }
-        })
-        .collect();
-
-    (dofus_id_to_internal_id_mapping, sets)
-}
-
-fn item_filter(items: &[(TokenStream, String)], filter: &[&str]) -> TokenStream {
-    let items = items
-        .iter()
-        .enumerate()
-        .filter(move |(_, x)| filter.contains(&x.1.as_str()))
-        .map(|(index, _)| quote! { ItemIndex(#index) });
-    quote! { &[#(#items),*]}
+    quote! {
+        Set {
+            name: Cow::Borrowed(#name),
+            start_at: #start_at,
+            bonuses: Cow::Borrowed(&[#(#bonuses),*]),
+        }
+    }
 }
 
 fn create_items() -> TokenStream {
-    let sets = parse_sets(include_bytes!("data/sets.json"));
-    let items = parse_items(
+    let (set_mappings, sets) = dofus_lab::parse_sets(include_bytes!("data/sets.json")).unwrap();
+    let items = dofus_lab::parse_items(
         &[
             include_bytes!("data/items.json"),
             include_bytes!("data/weapons.json"),
             include_bytes!("data/mounts.json"),
             include_bytes!("data/pets.json"),
         ],
-        &sets.0,
-    );
-
-    let mounts = item_filter(&items, &["Pet", "Petsmount", "Mount"]);
-    let weapons = item_filter(
-        &items,
-        &[
-            "Axe",
-            "Bow",
-            "Dagger",
-            "Hammer",
-            "Pickaxe",
-            "Scythe",
-            "Shovel",
-            "Soul stone",
-            "Staff",
-            "Sword",
-            "Tool",
-            "Wand",
-        ],
-    );
-    let hats = item_filter(&items, &["Hat"]);
-    let cloaks = item_filter(&items, &["Cloak", "Backpack"]);
-    let amulets = item_filter(&items, &["Amulet"]);
-    let rings = item_filter(&items, &["Ring"]);
-    let belts = item_filter(&items, &["Belt"]);
-    let boots = item_filter(&items, &["Boots"]);
-    let shields = item_filter(&items, &["Shield"]);
-    let dofus = item_filter(&items, &["Dofus", "Trophy", "Prysmaradite"]);
-
-    let items = items.iter().map(|x| &x.0);
-    let sets = sets.1.iter().map(|set| {
-        let name = &set.name;
-        let bonuses = &set.bonuses;
-        let start_at = set.bonus_start_at;
-
-        quote! {
-            Set {
-                name: #name,
-                start_at: #start_at,
-                bonuses: &[#(#bonuses),*],
-
-            }
-        }
+        &set_mappings,
+    )
+    .unwrap();
+
+    let item_types = dofus_lab::item_type_indexes(&items).map(|indexes| {
+        let indexes = indexes
+            .into_iter()
+            .map(|index| quote! { ItemIndex(#index) });
+        quote! { Cow::Borrowed(&[#(#indexes),*]) }
     });
 
+    let items = items.iter().map(quote_item);
+    let sets = sets.iter().map(quote_set);
+
     quote! {
 
         Items {
-            items: &[#(#items),*],
-            sets: &[#(#sets),*],
-            item_types: &[
-               #mounts, #weapons, #hats, #cloaks, #amulets, #rings, #belts, #boots, #shields, #dofus,
-            ]
+            items: Cow::Borrowed(&[#(#items),*]),
+            sets: Cow::Borrowed(&[#(#sets),*]),
+            item_types: Cow::Borrowed(&[#(#item_types),*]),
         }
 
     }
 }
 
-#[derive(Default)]
-struct Damage {
-    neutral: (i32, i32),
-    air: (i32, i32),
-    water: (i32, i32),
-    earth: (i32, i32),
-    fire: (i32, i32),
+impl ToTokens for ElementDamage {
+    fn to_tokens(&self, tokens: &mut TokenStream) {
+        let ElementDamage { min, max, steal } = self;
+
+        quote! { ElementDamage {
+            min: #min,
+            max: #max,
+            steal: #steal,
+        } }
+        .to_tokens(tokens);
+    }
 }
 
 impl ToTokens for Damage {
     fn to_tokens(&self, tokens: &mut TokenStream) {
-        let (n1, n2) = self.neutral;
-        let (a1, a2) = self.air;
-        let (w1, w2) = self.water;
-        let (e1, e2) = self.earth;
-        let (f1, f2) = self.fire;
+        let Damage {
+            neutral,
+            air,
+            water,
+            earth,
+            fire,
+        } = self;
 
         quote! { Damage {
-            neutral: ElementDamage {
-                min: #n1,
-                max: #n2,
-            },
-            air: ElementDamage {
-                min: #a1,
-                max: #a2,
-            },
-            water: ElementDamage {
-                min: #w1,
-                max: #w2,
-            },
-            earth: ElementDamage {
-                min: #e1,
-                max: #e2,
-            },
-            fire: ElementDamage {
-                min: #f1,
-                max: #f2,
-            },
+            neutral: #neutral,
+            air: #air,
+            water: #water,
+            earth: #earth,
+            fire: #fire,
+        } }
+        .to_tokens(tokens);
+    }
+}
+
+impl ToTokens for DamageLine {
+    fn to_tokens(&self, tokens: &mut TokenStream) {
+        let DamageLine {
+            element,
+            min,
+            max,
+            steal,
+        } = self;
+        let element = format_ident!("{element:?}");
+
+        quote! { DamageLine {
+            element: Element::#element,
+            min: #min,
+            max: #max,
+            steal: #steal,
+        } }
+        .to_tokens(tokens);
+    }
+}
+
+impl ToTokens for Weapon {
+    fn to_tokens(&self, tokens: &mut TokenStream) {
+        let Weapon {
+            ap_cost,
+            uses_per_turn,
+            min_range,
+            max_range,
+            base_crit,
+            crit_bonus_damage,
+            lines,
+        } = self;
+        let lines = lines.iter();
+
+        quote! { Weapon {
+            ap_cost: #ap_cost,
+            uses_per_turn: #uses_per_turn,
+            min_range: #min_range,
+            max_range: #max_range,
+            base_crit: #base_crit,
+            crit_bonus_damage: #crit_bonus_damage,
+            lines: Cow::Borrowed(&[#(#lines),*]),
         } }
         .to_tokens(tokens);
     }
@@ -412,17 +253,15 @@ impl ToTokens for Damage {
 
 fn get_effect(effect: &[DofusLabEffectElement]) -> Damage {
     let mut damage = Damage::default();
-    effect.iter().for_each(|x| {
-        let idx = match x.stat.as_str() {
-            "Neutral damage" | "Neutral steal" => &mut damage.neutral,
-            "Air damage" | "Air steal" => &mut damage.air,
-            "Water damage" | "Water steal" => &mut damage.water,
-            "Earth damage" | "Earth steal" => &mut damage.earth,
-            "Fire damage" | "Fire steal" => &mut damage.fire,
-            _ => return,
-        };
-        *idx = (x.min_stat.unwrap_or(x.max_stat.0), x.max_stat.0);
-    });
+    for x in effect {
+        if let Some(element) = damage.element_mut(&x.stat) {
+            *element = ElementDamage {
+                min: x.min_stat.unwrap_or(x.max_stat.0),
+                max: x.max_stat.0,
+                steal: Damage::is_steal(&x.stat),
+            };
+        }
+    }
 
     damage
 }
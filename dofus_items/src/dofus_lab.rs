@@ -0,0 +1,481 @@
+//! Parsing of the DofusLab JSON dumps.
+//!
+//! This module is shared between the build script, which turns the parsed data into the
+//! compiled in `ITEMS`, and the runtime loader, so both always agree on how a dump is read.
+
+use std::{borrow::Cow, collections::HashMap};
+
+use dofus_characteristics::{
+    BooleanOperator, Characteristic, Element, NullRestriction, Operator, Restriction,
+    RestrictionLeaf, SetBonusRestriction, Stat, StatConversionError,
+};
+use serde::{Deserialize, Serialize};
+
+/// The DofusLab item types that can go in each slot type, in the same order as `ItemType`
+pub const ITEM_TYPE_NAMES: [&[&str]; 10] = [
+    &["Pet", "Petsmount", "Mount"],
+    &[
+        "Axe",
+        "Bow",
+        "Dagger",
+        "Hammer",
+        "Pickaxe",
+        "Scythe",
+        "Shovel",
+        "Soul stone",
+        "Staff",
+        "Sword",
+        "Tool",
+        "Wand",
+    ],
+    &["Hat"],
+    &["Cloak", "Backpack"],
+    &["Amulet"],
+    &["Ring"],
+    &["Belt"],
+    &["Boots"],
+    &["Shield"],
+    &["Dofus", "Trophy", "Prysmaradite"],
+];
+
+#[derive(Debug, thiserror::Error)]
+pub enum DofusLabError {
+    #[error("could not decode DofusLab data, {0}")]
+    Decode(#[from] serde_json::Error),
+    #[error("unsupported stat {0}")]
+    UnsupportedStat(String),
+    #[error("unsupported restriction operator {0}")]
+    UnsupportedOperator(String),
+    #[error("item {0} has no image")]
+    MissingImage(String),
+    #[error("set {0} has an invalid bonus")]
+    InvalidSetBonus(String),
+}
+
+#[derive(Deserialize, Debug)]
+pub struct DofusLabConditions {
+    conditions: serde_json::Map<String, serde_json::Value>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct DofusLabLocalised {
+    pub en: String,
+}
+
+#[allow(non_snake_case)]
+#[derive(Deserialize, Debug)]
+struct DofusLabItemStats {
+    stat: String,
+    maxStat: i32,
+}
+
+#[allow(non_snake_case)]
+#[derive(Deserialize, Debug)]
+struct DofusLabItem {
+    name: DofusLabLocalised,
+    itemType: String,
+    setID: Option<String>,
+    stats: Option<Vec<DofusLabItemStats>>,
+    level: i32,
+    conditions: Option<DofusLabConditions>,
+    imageUrl: Option<String>,
+    weaponStats: Option<DofusLabWeaponStats>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct DofusLabWeaponStats {
+    ap_cost: i32,
+    uses_per_turn: i32,
+    min_range: Option<i32>,
+    max_range: i32,
+    base_crit_chance: i32,
+    crit_bonus_damage: i32,
+    #[serde(rename = "weapon_effects")]
+    weapon_effects: Vec<DofusLabEffectElement>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct DofusLabEffectElement {
+    pub stat: String,
+    pub min_stat: Option<i32>,
+    pub max_stat: StringI32,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct StringI32(pub i32);
+
+impl<'de> Deserialize<'de> for StringI32 {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        use serde_json::Value;
+
+        Ok(match Value::deserialize(deserializer)? {
+            Value::String(s) => s.parse().map(StringI32).map_err(serde::de::Error::custom)?,
+            Value::Number(n) => n
+                .as_i64()
+                .ok_or(serde::de::Error::custom("wrong type"))?
+                .try_into()
+                .map(StringI32)
+                .map_err(serde::de::Error::custom)?,
+            _ => return Err(serde::de::Error::custom("wrong type")),
+        })
+    }
+}
+
+#[derive(Deserialize)]
+struct DofusLabStatRestriction {
+    stat: String,
+    operator: String,
+    value: i32,
+}
+
+#[derive(Debug, Deserialize)]
+struct DofusLabSetStat {
+    stat: Option<String>,
+    value: Option<i32>,
+}
+
+#[derive(Debug, Deserialize)]
+struct DofusLabSet {
+    name: DofusLabLocalised,
+    id: String,
+    bonuses: HashMap<String, Vec<DofusLabSetStat>>,
+}
+
+/// An item restriction as it appears in the dump
+#[derive(Debug, Clone)]
+pub enum RestrictionTree {
+    Null,
+    Leaf(RestrictionLeaf),
+    SetBonus(SetBonusRestriction),
+    Set {
+        operator: BooleanOperator,
+        restrictions: Vec<RestrictionTree>,
+    },
+}
+
+impl Restriction for RestrictionTree {
+    fn accepts(&self, characteristics: &Characteristic, set_bonus: i32) -> i32 {
+        match self {
+            RestrictionTree::Null => NullRestriction.accepts(characteristics, set_bonus),
+            RestrictionTree::Leaf(leaf) => leaf.accepts(characteristics, set_bonus),
+            RestrictionTree::SetBonus(restriction) => {
+                restriction.accepts(characteristics, set_bonus)
+            }
+            RestrictionTree::Set {
+                operator,
+                restrictions,
+            } => operator.combine(
+                restrictions
+                    .iter()
+                    .map(|restriction| restriction.accepts(characteristics, set_bonus)),
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct Damage {
+    pub neutral: ElementDamage,
+    pub air: ElementDamage,
+    pub water: ElementDamage,
+    pub earth: ElementDamage,
+    pub fire: ElementDamage,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct ElementDamage {
+    pub min: i32,
+    pub max: i32,
+    /// The caster is healed for half of the damage dealt
+    pub steal: bool,
+}
+
+impl Damage {
+    /// The element a damage line like "Earth damage" or "Air steal" hits in, if it is one
+    pub fn element_mut(&mut self, stat: &str) -> Option<&mut ElementDamage> {
+        Some(match stat {
+            "Neutral damage" | "Neutral steal" => &mut self.neutral,
+            "Air damage" | "Air steal" => &mut self.air,
+            "Water damage" | "Water steal" => &mut self.water,
+            "Earth damage" | "Earth steal" => &mut self.earth,
+            "Fire damage" | "Fire steal" => &mut self.fire,
+            _ => return None,
+        })
+    }
+
+    /// Whether a damage line is a steal rather than plain damage
+    pub fn is_steal(stat: &str) -> bool {
+        stat.ends_with(" steal")
+    }
+}
+
+/// One line of weapon damage. A weapon with several lines hits once per line, even when two
+/// of them are in the same element.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct DamageLine {
+    pub element: Element,
+    pub min: i32,
+    pub max: i32,
+    /// The caster is healed for half of the damage dealt
+    pub steal: bool,
+}
+
+impl DamageLine {
+    /// The line for an effect like "Earth damage" or "Air steal", if it is one
+    fn parse(effect: &DofusLabEffectElement) -> Option<Self> {
+        let (element, kind) = effect.stat.split_once(' ')?;
+        let element = match element {
+            "Neutral" => Element::Neutral,
+            "Air" => Element::Air,
+            "Water" => Element::Water,
+            "Earth" => Element::Earth,
+            "Fire" => Element::Fire,
+            _ => return None,
+        };
+        let steal = match kind {
+            "damage" => false,
+            "steal" => true,
+            _ => return None,
+        };
+
+        Some(DamageLine {
+            element,
+            min: effect.min_stat.unwrap_or(effect.max_stat.0),
+            max: effect.max_stat.0,
+            steal,
+        })
+    }
+}
+
+/// What a weapon does when it is used to attack
+#[derive(Debug, Clone, Serialize)]
+pub struct Weapon {
+    pub ap_cost: i32,
+    pub uses_per_turn: i32,
+    pub min_range: i32,
+    pub max_range: i32,
+    /// Critical hit chance in percent before any `Critical` from the rest of the set
+    pub base_crit: i32,
+    /// Extra damage added to every damage line on a critical hit
+    pub crit_bonus_damage: i32,
+    /// The damage lines in the order the weapon lists them
+    pub lines: Cow<'static, [DamageLine]>,
+}
+
+impl Weapon {
+    fn parse(stats: &DofusLabWeaponStats) -> Self {
+        Weapon {
+            ap_cost: stats.ap_cost,
+            uses_per_turn: stats.uses_per_turn,
+            min_range: stats.min_range.unwrap_or(stats.max_range),
+            max_range: stats.max_range,
+            base_crit: stats.base_crit_chance,
+            crit_bonus_damage: stats.crit_bonus_damage,
+            lines: stats
+                .weapon_effects
+                .iter()
+                .filter_map(DamageLine::parse)
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ParsedItem {
+    pub name: String,
+    pub item_type: String,
+    pub stats: Characteristic,
+    pub level: i32,
+    pub set_id: Option<usize>,
+    pub restriction: RestrictionTree,
+    pub image_url: String,
+    pub weapon: Option<Weapon>,
+}
+
+#[derive(Debug)]
+pub struct ParsedSet {
+    pub name: String,
+    pub bonus_start_at: usize,
+    pub bonuses: Vec<Characteristic>,
+}
+
+fn parse_restriction(
+    value: &serde_json::Map<String, serde_json::Value>,
+) -> Result<RestrictionTree, DofusLabError> {
+    if value.is_empty() {
+        return Ok(RestrictionTree::Null);
+    }
+
+    let children = |restrictions: &serde_json::Value| {
+        restrictions
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|r| r.as_object())
+            .map(parse_restriction)
+            .collect::<Result<Vec<_>, _>>()
+    };
+
+    if let Some(and_restriction) = value.get("and") {
+        Ok(RestrictionTree::Set {
+            operator: BooleanOperator::And,
+            restrictions: children(and_restriction)?,
+        })
+    } else if let Some(or_restriction) = value.get("or") {
+        Ok(RestrictionTree::Set {
+            operator: BooleanOperator::Or,
+            restrictions: children(or_restriction)?,
+        })
+    } else {
+        let stat: DofusLabStatRestriction =
+            serde_json::from_value(serde_json::Value::Object(value.clone()))?;
+        let operator = match stat.operator.as_str() {
+            "<" => Operator::LessThan,
+            ">" => Operator::GreaterThan,
+            _ => return Err(DofusLabError::UnsupportedOperator(stat.operator)),
+        };
+
+        if stat.stat == "SET_BONUS" {
+            return Ok(RestrictionTree::SetBonus(SetBonusRestriction {
+                operator,
+                value: stat.value,
+            }));
+        }
+
+        match Stat::try_from(stat.stat.as_str()) {
+            Ok(s) => Ok(RestrictionTree::Leaf(RestrictionLeaf {
+                operator,
+                stat: s,
+                value: stat.value,
+            })),
+            Err(StatConversionError::IntentionallyIgnored) => Ok(RestrictionTree::Null),
+            Err(StatConversionError::Unknown) => Err(DofusLabError::UnsupportedStat(stat.stat)),
+        }
+    }
+}
+
+fn parse_stat(stat: &str) -> Result<Stat, DofusLabError> {
+    stat.try_into()
+        .map_err(|_| DofusLabError::UnsupportedStat(stat.to_owned()))
+}
+
+/// Parses the item files, `set_mappings` maps DofusLab set ids to the index of the set
+pub fn parse_items(
+    data: &[&[u8]],
+    set_mappings: &HashMap<String, usize>,
+) -> Result<Vec<ParsedItem>, DofusLabError> {
+    let mut items = Vec::new();
+    for data in data {
+        items.extend(serde_json::from_slice::<Vec<DofusLabItem>>(data)?);
+    }
+
+    items
+        .into_iter()
+        .map(|item| {
+            let mut stats = Characteristic::new();
+            for stat in item.stats.iter().flatten() {
+                stats[parse_stat(&stat.stat)?] = stat.maxStat;
+            }
+
+            let restriction = match &item.conditions {
+                Some(conditions) => parse_restriction(&conditions.conditions)?,
+                None => RestrictionTree::Null,
+            };
+
+            let set_id = item
+                .setID
+                .as_ref()
+                .and_then(|id| set_mappings.get(id).copied());
+
+            let image_url = item
+                .imageUrl
+                .ok_or_else(|| DofusLabError::MissingImage(item.name.en.clone()))?;
+
+            Ok(ParsedItem {
+                name: item.name.en,
+                item_type: item.itemType,
+                stats,
+                level: item.level,
+                set_id,
+                restriction,
+                image_url,
+                weapon: item.weaponStats.as_ref().map(Weapon::parse),
+            })
+        })
+        .collect()
+}
+
+/// Parses the set file, returning the mapping of DofusLab set ids to the index in the set list
+pub fn parse_sets(data: &[u8]) -> Result<(HashMap<String, usize>, Vec<ParsedSet>), DofusLabError> {
+    let data: Vec<DofusLabSet> = serde_json::from_slice(data)?;
+
+    let mut dofus_id_to_internal_id_mapping = HashMap::default();
+
+    let sets = data
+        .into_iter()
+        .enumerate()
+        .map(|(idx, set)| {
+            let invalid_bonus = || DofusLabError::InvalidSetBonus(set.name.en.clone());
+
+            let item_count_to_bonus = set
+                .bonuses
+                .iter()
+                .map(|(number_of_items, bonus)| {
+                    let mut stats = Characteristic::new();
+                    for stat in bonus {
+                        if let (Some(stat), Some(value)) = (&stat.stat, stat.value) {
+                            stats[parse_stat(stat)?] = value;
+                        }
+                    }
+
+                    Ok((number_of_items.parse().map_err(|_| invalid_bonus())?, stats))
+                })
+                .collect::<Result<Vec<(usize, _)>, DofusLabError>>()?;
+
+            let minimum_number_of_items = item_count_to_bonus
+                .iter()
+                .map(|x| x.0)
+                .min()
+                .ok_or_else(invalid_bonus)?;
+
+            let maximum_number_of_items = item_count_to_bonus
+                .iter()
+                .map(|x| x.0)
+                .max()
+                .ok_or_else(invalid_bonus)?;
+
+            let mut bonuses =
+                vec![Characteristic::new(); maximum_number_of_items - minimum_number_of_items + 1];
+            for (idx, bonus) in item_count_to_bonus.into_iter() {
+                bonuses[idx - minimum_number_of_items] = bonus;
+            }
+
+            dofus_id_to_internal_id_mapping.insert(set.id, idx);
+
+            Ok(ParsedSet {
+                name: set.name.en,
+                bonus_start_at: minimum_number_of_items,
+                bonuses,
+            })
+        })
+        .collect::<Result<_, DofusLabError>>()?;
+
+    Ok((dofus_id_to_internal_id_mapping, sets))
+}
+
+/// The indexes of the items that fit in each slot type, in the same order as `ItemType`
+pub fn item_type_indexes(items: &[ParsedItem]) -> [Vec<usize>; 10] {
+    ITEM_TYPE_NAMES.map(|filter| {
+        items
+            .iter()
+            .enumerate()
+            .filter(|(_, item)| filter.contains(&item.item_type.as_str()))
+            .map(|(index, _)| index)
+            .collect()
+    })
+}
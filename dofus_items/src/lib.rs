@@ -1,19 +1,44 @@
-use std::ops::Index;
+use std::{borrow::Cow, ops::Index};
 
 mod data;
+mod dofus_lab;
+mod loader;
 
 use dofus_characteristics::*;
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug)]
+pub use dofus_lab::{Damage, DamageLine, DofusLabError, ElementDamage, Weapon};
+pub use loader::{DofusLabData, LoadError};
+
+#[derive(Debug, Clone)]
 pub struct Item {
-    pub name: &'static str,
-    pub item_type: &'static str,
+    pub name: Cow<'static, str>,
+    pub item_type: Cow<'static, str>,
     pub stats: Characteristic,
     pub level: i32,
     pub set_id: Option<SetIndex>,
-    pub restriction: &'static (dyn Restriction + Sync + Send),
-    pub image_url: &'static str,
+    pub restriction: ItemRestriction,
+    pub image_url: Cow<'static, str>,
+    /// The base damage of the item if it is a weapon
+    pub weapon: Option<Weapon>,
+}
+
+/// The restriction of an item, either compiled in or loaded at runtime
+#[derive(Debug, Clone)]
+pub enum ItemRestriction {
+    Compiled(&'static (dyn Restriction + Sync + Send)),
+    Loaded(dofus_lab::RestrictionTree),
+}
+
+impl Restriction for ItemRestriction {
+    fn accepts(&self, characteristics: &Characteristic, set_bonus: i32) -> i32 {
+        match self {
+            ItemRestriction::Compiled(restriction) => {
+                restriction.accepts(characteristics, set_bonus)
+            }
+            ItemRestriction::Loaded(restriction) => restriction.accepts(characteristics, set_bonus),
+        }
+    }
 }
 
 impl Index<ItemIndex> for Items {
@@ -28,7 +53,7 @@ impl Index<ItemType> for Items {
     type Output = [ItemIndex];
 
     fn index(&self, index: ItemType) -> &Self::Output {
-        self.item_types[index as usize]
+        &self.item_types[index as usize]
     }
 }
 
@@ -47,6 +72,16 @@ impl Items {
             .enumerate()
             .map(|(idx, item)| (ItemIndex::new_from_id(idx), item))
     }
+
+    pub fn weapon(&self, index: ItemIndex) -> Option<&Weapon> {
+        self[index].weapon.as_ref()
+    }
+
+    pub fn weapons(&self) -> impl Iterator<Item = (ItemIndex, &Weapon)> {
+        self[ItemType::Weapon]
+            .iter()
+            .filter_map(|&index| Some((index, self.weapon(index)?)))
+    }
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy, PartialOrd, Ord, Hash, Serialize, Deserialize)]
@@ -90,11 +125,11 @@ impl ItemIndex {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Items {
-    items: &'static [Item],
-    sets: &'static [Set],
-    item_types: &'static [&'static [ItemIndex]],
+    items: Cow<'static, [Item]>,
+    sets: Cow<'static, [Set]>,
+    item_types: Cow<'static, [Cow<'static, [ItemIndex]>]>,
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
@@ -129,11 +164,11 @@ impl From<usize> for ItemType {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Set {
-    pub name: &'static str,
+    pub name: Cow<'static, str>,
     start_at: usize,
-    bonuses: &'static [Characteristic],
+    bonuses: Cow<'static, [Characteristic]>,
 }
 
 impl Set {
@@ -172,19 +207,30 @@ pub struct Effect {
     pub critical: Option<Damage>,
 }
 
-#[derive(Serialize)]
-pub struct Damage {
-    pub neutral: ElementDamage,
-    pub air: ElementDamage,
-    pub water: ElementDamage,
-    pub earth: ElementDamage,
-    pub fire: ElementDamage,
+impl Class {
+    pub fn spell(&self, name: &str) -> Option<&'static Spell> {
+        self.spells
+            .iter()
+            .find(|spell| spell.name.eq_ignore_ascii_case(name))
+    }
 }
 
-#[derive(Serialize)]
-pub struct ElementDamage {
-    pub min: i32,
-    pub max: i32,
+impl Spell {
+    /// The effect of the spell for a character of the given level, which is the highest level
+    /// of the spell the character has unlocked
+    pub fn effect(&self, character_level: i32) -> Option<&'static Effect> {
+        self.effects
+            .iter()
+            .filter(|effect| effect.level <= character_level)
+            .max_by_key(|effect| effect.level)
+    }
+}
+
+/// The class with the given name, ignoring case
+pub fn class(name: &str) -> Option<&'static Class> {
+    SPELLS
+        .iter()
+        .find(|class| class.name.eq_ignore_ascii_case(name))
 }
 
 pub static SPELLS: &[Class] = data::SPELLS;
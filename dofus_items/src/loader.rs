@@ -0,0 +1,174 @@
+use std::{
+    borrow::Cow,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    dofus_lab::{self, DofusLabError},
+    Item, ItemIndex, ItemRestriction, Items, Set, SetIndex,
+};
+
+/// The contents of the DofusLab JSON files that make up an item database
+pub struct DofusLabData<'a> {
+    pub items: &'a [u8],
+    pub weapons: &'a [u8],
+    pub mounts: &'a [u8],
+    pub pets: &'a [u8],
+    pub sets: &'a [u8],
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum LoadError {
+    #[error("could not read {}, {source}", path.display())]
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error(transparent)]
+    DofusLab(#[from] DofusLabError),
+}
+
+impl Items {
+    /// Builds an item database from DofusLab data at runtime rather than using the compiled in `ITEMS`
+    pub fn from_dofus_lab(data: &DofusLabData) -> Result<Items, DofusLabError> {
+        let (set_mappings, sets) = dofus_lab::parse_sets(data.sets)?;
+        let items = dofus_lab::parse_items(
+            &[data.items, data.weapons, data.mounts, data.pets],
+            &set_mappings,
+        )?;
+
+        let item_types = dofus_lab::item_type_indexes(&items)
+            .into_iter()
+            .map(|indexes| {
+                indexes
+                    .into_iter()
+                    .map(ItemIndex::new_from_id)
+                    .collect::<Vec<_>>()
+                    .into()
+            })
+            .collect::<Vec<_>>();
+
+        let items = items
+            .into_iter()
+            .map(|item| Item {
+                name: Cow::Owned(item.name),
+                item_type: Cow::Owned(item.item_type),
+                stats: item.stats,
+                level: item.level,
+                set_id: item.set_id.map(SetIndex),
+                restriction: ItemRestriction::Loaded(item.restriction),
+                image_url: Cow::Owned(item.image_url),
+                weapon: item.weapon,
+            })
+            .collect::<Vec<_>>();
+
+        let sets = sets
+            .into_iter()
+            .map(|set| Set {
+                name: Cow::Owned(set.name),
+                start_at: set.bonus_start_at,
+                bonuses: Cow::Owned(set.bonuses),
+            })
+            .collect::<Vec<_>>();
+
+        Ok(Items {
+            items: items.into(),
+            sets: sets.into(),
+            item_types: item_types.into(),
+        })
+    }
+
+    /// Loads `items.json`, `weapons.json`, `mounts.json`, `pets.json` and `sets.json` from a
+    /// DofusLab data directory
+    pub fn load_dofus_lab(directory: impl AsRef<Path>) -> Result<Items, LoadError> {
+        let directory = directory.as_ref();
+        let read = |name: &str| {
+            let path = directory.join(name);
+            std::fs::read(&path).map_err(|source| LoadError::Io { path, source })
+        };
+
+        Ok(Self::from_dofus_lab(&DofusLabData {
+            items: &read("items.json")?,
+            weapons: &read("weapons.json")?,
+            mounts: &read("mounts.json")?,
+            pets: &read("pets.json")?,
+            sets: &read("sets.json")?,
+        })?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use dofus_characteristics::{Element, Restriction};
+
+    use crate::{DamageLine, ItemType, Items, ITEMS};
+
+    #[test]
+    fn loaded_items_match_compiled_items() {
+        let items = Items::load_dofus_lab(concat!(env!("CARGO_MANIFEST_DIR"), "/data")).unwrap();
+
+        assert_eq!(items.iter().count(), ITEMS.iter().count());
+        for ((_, loaded), (_, compiled)) in items.iter().zip(ITEMS.iter()) {
+            assert_eq!(loaded.name, compiled.name);
+            assert_eq!(loaded.set_id, compiled.set_id);
+            assert_eq!(
+                loaded.stats.iter().collect::<Vec<_>>(),
+                compiled.stats.iter().collect::<Vec<_>>()
+            );
+            assert_eq!(
+                loaded.restriction.accepts(&compiled.stats, 0),
+                compiled.restriction.accepts(&compiled.stats, 0)
+            );
+            assert_eq!(
+                loaded.weapon.as_ref().map(|weapon| &weapon.lines),
+                compiled.weapon.as_ref().map(|weapon| &weapon.lines)
+            );
+        }
+
+        for item_type in (0..10).map(ItemType::from) {
+            assert_eq!(items[item_type], ITEMS[item_type]);
+        }
+    }
+
+    #[test]
+    fn weapons_keep_every_damage_line() {
+        let (_, sword) = ITEMS
+            .iter()
+            .find(|(_, item)| item.name == "Bearbaric Sword")
+            .unwrap();
+        let line = DamageLine {
+            element: Element::Neutral,
+            min: 7,
+            max: 12,
+            steal: false,
+        };
+
+        assert_eq!(*sword.weapon.as_ref().unwrap().lines, [line; 3]);
+    }
+
+    #[test]
+    fn steal_lines_stay_separate_from_damage_lines() {
+        let (_, hammer) = ITEMS
+            .iter()
+            .find(|(_, item)| item.name == "Crick Hammer")
+            .unwrap();
+
+        assert_eq!(
+            *hammer.weapon.as_ref().unwrap().lines,
+            [
+                DamageLine {
+                    element: Element::Neutral,
+                    min: 25,
+                    max: 42,
+                    steal: false,
+                },
+                DamageLine {
+                    element: Element::Neutral,
+                    min: 4,
+                    max: 6,
+                    steal: true,
+                },
+            ]
+        );
+    }
+}
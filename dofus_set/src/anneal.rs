@@ -1,3 +1,154 @@
+use std::ops::{ControlFlow, Range};
+
+use rand::Rng;
+use serde::Deserialize;
+
+/// The temperature every schedule cools down to by the end of the run
+const FINAL_TEMPERATURE: f64 = 0.01;
+/// Each reheat goes back up to this fraction of the temperature the last one started from
+const REHEAT_FACTOR: f64 = 0.5;
+
+/// How the temperature falls from the initial temperature over the course of a run
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum CoolingSchedule {
+    /// Stays hot for most of the run and then cools quickly, reaching the final temperature 95%
+    /// of the way through. A larger quench stays hot for longer.
+    QuenchedExponential {
+        quench: f64,
+    },
+    /// Falls by the same factor every iteration
+    Geometric,
+    Linear,
+    /// Cools geometrically, and reheats whenever fewer than `min_acceptance` of the moves in the
+    /// last `window` iterations were accepted, so the search can escape where it got stuck. Each
+    /// reheat starts lower than the one before and cools to the final temperature by the end.
+    #[serde(rename_all = "camelCase")]
+    Reheating {
+        window: i64,
+        min_acceptance: f64,
+    },
+}
+
+/// Where the temperature was last reheated to, which the reheating schedule cools down from
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Heat {
+    /// How far through the run the reheat was, between 0 and 1
+    pub from: f64,
+    /// The temperature reheated to as a fraction of the initial temperature
+    pub peak: f64,
+}
+
+impl Default for Heat {
+    fn default() -> Self {
+        Heat { from: 0., peak: 1. }
+    }
+}
+
+impl Default for CoolingSchedule {
+    fn default() -> Self {
+        CoolingSchedule::QuenchedExponential { quench: 5. }
+    }
+}
+
+impl CoolingSchedule {
+    /// The temperature `fraction` of the way through the run, where `fraction` is between 0 and 1
+    pub fn temperature(&self, initial_temperature: f64, fraction: f64) -> f64 {
+        self.reheated_temperature(initial_temperature, fraction, Heat::default())
+    }
+
+    /// The temperature `fraction` of the way through a run that was last reheated at `heat`,
+    /// which only the reheating schedule is affected by
+    pub fn reheated_temperature(&self, initial_temperature: f64, fraction: f64, heat: Heat) -> f64 {
+        let geometric = |from: f64, fraction: f64| from * (FINAL_TEMPERATURE / from).powf(fraction);
+
+        match *self {
+            CoolingSchedule::QuenchedExponential { quench } => {
+                let time_constant =
+                    (FINAL_TEMPERATURE / initial_temperature).ln() / 0.95_f64.powf(quench);
+                initial_temperature * (time_constant * fraction.powf(quench)).exp()
+            }
+            CoolingSchedule::Geometric => geometric(initial_temperature, fraction),
+            CoolingSchedule::Linear => {
+                initial_temperature + (FINAL_TEMPERATURE - initial_temperature) * fraction
+            }
+            CoolingSchedule::Reheating { .. } => {
+                let cooled = (fraction - heat.from) / (1. - heat.from);
+                geometric(initial_temperature * heat.peak, cooled.clamp(0., 1.))
+            }
+        }
+    }
+
+    /// The number of iterations the acceptance rate is measured over and the rate below which
+    /// the run is reheated, for the schedules that reheat
+    pub fn reheat_below(&self) -> Option<(i64, f64)> {
+        match *self {
+            CoolingSchedule::Reheating {
+                window,
+                min_acceptance,
+            } => Some((window.max(1), min_acceptance)),
+            _ => None,
+        }
+    }
+}
+
+/// A snapshot of a running optimisation
+pub struct Progress<'a, T> {
+    pub iteration: i64,
+    pub iterations: i64,
+    pub temperature: f64,
+    pub energy: f64,
+    pub best: &'a T,
+    pub best_energy: f64,
+}
+
+/// Watches an optimisation as it runs, and can stop it early
+pub trait Observer<T> {
+    /// The number of iterations between calls to `progress`
+    fn interval(&self) -> i64;
+    fn progress(&mut self, progress: &Progress<T>) -> ControlFlow<()>;
+
+    /// Called with every state the optimisation moves to
+    fn accepted(&mut self, _state: &T, _energy: f64) {}
+}
+
+/// Doesn't watch at all
+impl<T> Observer<T> for () {
+    fn interval(&self) -> i64 {
+        i64::MAX
+    }
+
+    fn progress(&mut self, _progress: &Progress<T>) -> ControlFlow<()> {
+        ControlFlow::Continue(())
+    }
+}
+
+/// Where a run of annealing got to, the state it's on and the best state it has visited
+#[derive(Clone, Debug)]
+pub struct Annealed<T> {
+    pub last: T,
+    pub last_energy: f64,
+    pub best: T,
+    pub best_energy: f64,
+    /// Whether the observer stopped the run before it finished
+    pub stopped: bool,
+    /// Where a reheating schedule got to, so a run that is picked up again carries on from it
+    pub heat: Heat,
+}
+
+impl<T: Clone> Annealed<T> {
+    pub fn new(state: T, energy: f64) -> Self {
+        Annealed {
+            last: state.clone(),
+            last_energy: energy,
+            best: state,
+            best_energy: energy,
+            stopped: false,
+            heat: Heat::default(),
+        }
+    }
+}
+
 pub trait Anneal<T> {
     type Error;
 
@@ -9,27 +160,239 @@ pub trait Anneal<T> {
         }
     }
 
-    fn random(&self) -> f64;
+    /// The temperature `fraction` of the way through the run, last reheated at `heat`
+    fn temperature(&self, fraction: f64, heat: Heat) -> f64;
+
+    /// The number of iterations to measure the acceptance rate over and the rate below which
+    /// the run is reheated, if it ever is
+    fn reheat_below(&self) -> Option<(i64, f64)> {
+        None
+    }
+    fn neighbour<R: Rng>(&self, state: &T, rng: &mut R) -> Result<(T, f64), Self::Error>;
+
+    /// Whether a state is one that could actually be used, a valid state is always kept as the
+    /// best over an invalid one whatever their energies
+    fn is_valid(&self, _state: &T) -> bool {
+        true
+    }
 
-    fn temperature(&self, iteration: f64) -> f64;
-    fn neighbour(&self, state: &T) -> Result<(T, f64), Self::Error>;
+    fn optimise<R: Rng>(
+        &self,
+        initial_state: (T, f64),
+        num_iterations: i64,
+        rng: &mut R,
+        observer: &mut impl Observer<T>,
+    ) -> Result<Annealed<T>, Self::Error>
+    where
+        T: Clone,
+    {
+        self.anneal(
+            Annealed::new(initial_state.0, initial_state.1),
+            0..num_iterations,
+            num_iterations,
+            rng,
+            observer,
+        )
+    }
 
-    fn optimise(&self, initial_state: (T, f64), num_iterations: i64) -> Result<T, Self::Error> {
+    /// Runs the given part of a schedule of `num_iterations` iterations, so a run can be split
+    /// up and picked up again from a different state
+    fn anneal<R: Rng>(
+        &self,
+        start: Annealed<T>,
+        iterations: Range<i64>,
+        num_iterations: i64,
+        rng: &mut R,
+        observer: &mut impl Observer<T>,
+    ) -> Result<Annealed<T>, Self::Error>
+    where
+        T: Clone,
+    {
         let number_of_iterations = num_iterations as f64;
-        let mut current_state = initial_state.0;
-        let mut current_state_energy = initial_state.1;
-        for iteration in 0..num_iterations {
-            let iteration = iteration as f64;
-            let temperature = self.temperature((iteration + 1.0) / number_of_iterations);
-            let (neighbour, neighbour_energy) = self.neighbour(&current_state)?;
+        let interval = observer.interval().max(1);
+        let Annealed {
+            last: mut current_state,
+            last_energy: mut current_state_energy,
+            mut best,
+            mut best_energy,
+            stopped: _,
+            mut heat,
+        } = start;
+        let mut stopped = false;
+        let mut best_valid = self.is_valid(&best);
+        let reheat_below = self.reheat_below();
+        let mut accepted_in_window = 0;
+
+        for iteration in iterations {
+            let fraction = (iteration as f64 + 1.0) / number_of_iterations;
+            let temperature = self.temperature(fraction, heat);
+
+            if iteration % interval == 0 {
+                let progress = Progress {
+                    iteration,
+                    iterations: num_iterations,
+                    temperature,
+                    energy: current_state_energy,
+                    best: &best,
+                    best_energy,
+                };
+
+                if observer.progress(&progress).is_break() {
+                    stopped = true;
+                    break;
+                }
+            }
+
+            let (neighbour, neighbour_energy) = self.neighbour(&current_state, rng)?;
             let acceptance_rate =
                 Self::accept_probability(current_state_energy, neighbour_energy, temperature);
-            if acceptance_rate >= self.random() {
+            if acceptance_rate >= rng.gen_range(0.0..1.0) {
                 current_state = neighbour;
                 current_state_energy = neighbour_energy;
+                accepted_in_window += 1;
+                observer.accepted(&current_state, current_state_energy);
+
+                // only check validity when it could change which state is best
+                if current_state_energy < best_energy || !best_valid {
+                    let valid = self.is_valid(&current_state);
+                    if (valid && !best_valid)
+                        || (valid == best_valid && current_state_energy < best_energy)
+                    {
+                        best = current_state.clone();
+                        best_energy = current_state_energy;
+                        best_valid = valid;
+                    }
+                }
+            }
+
+            if let Some((window, min_acceptance)) = reheat_below {
+                if (iteration + 1) % window == 0 {
+                    let acceptance = accepted_in_window as f64 / window as f64;
+                    let reheated = Heat {
+                        from: fraction,
+                        peak: heat.peak * REHEAT_FACTOR,
+                    };
+                    // only reheat if it actually makes the run hotter
+                    if acceptance < min_acceptance
+                        && self.temperature(fraction, reheated) > temperature
+                    {
+                        heat = reheated;
+                    }
+                    accepted_in_window = 0;
+                }
             }
         }
 
-        Ok(current_state)
+        Ok(Annealed {
+            last: current_state,
+            last_energy: current_state_energy,
+            best,
+            best_energy,
+            stopped,
+            heat,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+
+    /// Counts upwards with lower energy each step, but only up to 5 is valid
+    struct CountUp;
+
+    impl Anneal<i32> for CountUp {
+        type Error = ();
+
+        fn temperature(&self, _fraction: f64, _heat: Heat) -> f64 {
+            1.
+        }
+
+        fn neighbour<R: Rng>(&self, state: &i32, _rng: &mut R) -> Result<(i32, f64), ()> {
+            Ok((state + 1, -(state + 1) as f64))
+        }
+
+        fn is_valid(&self, state: &i32) -> bool {
+            *state <= 5
+        }
+    }
+
+    #[test]
+    fn keeps_the_best_valid_state() {
+        let annealed = CountUp
+            .optimise((0, 0.), 10, &mut StdRng::seed_from_u64(0), &mut ())
+            .unwrap();
+
+        assert_eq!(annealed.last, 10);
+        assert_eq!(annealed.best, 5);
+        assert_eq!(annealed.best_energy, -5.);
+    }
+
+    #[test]
+    fn schedules_cool_from_the_initial_temperature() {
+        let schedules = [
+            CoolingSchedule::default(),
+            CoolingSchedule::Geometric,
+            CoolingSchedule::Linear,
+            REHEATING,
+        ];
+
+        for schedule in schedules {
+            assert!((schedule.temperature(1000., 0.) - 1000.).abs() < 1e-9);
+            assert!(schedule.temperature(1000., 1.) <= FINAL_TEMPERATURE + 1e-9);
+        }
+
+        let heat = Heat {
+            from: 0.5,
+            peak: 0.5,
+        };
+        assert!((REHEATING.reheated_temperature(1000., 0.5, heat) - 500.).abs() < 1e-9);
+        assert!(REHEATING.reheated_temperature(1000., 1., heat) <= FINAL_TEMPERATURE + 1e-9);
+    }
+
+    const REHEATING: CoolingSchedule = CoolingSchedule::Reheating {
+        window: 10,
+        min_acceptance: 0.2,
+    };
+
+    /// Only ever gets worse, by `worse_by` a move
+    struct Stuck {
+        worse_by: f64,
+    }
+
+    impl Anneal<i32> for Stuck {
+        type Error = ();
+
+        fn temperature(&self, fraction: f64, heat: Heat) -> f64 {
+            REHEATING.reheated_temperature(100., fraction, heat)
+        }
+
+        fn reheat_below(&self) -> Option<(i64, f64)> {
+            REHEATING.reheat_below()
+        }
+
+        fn neighbour<R: Rng>(&self, state: &i32, _rng: &mut R) -> Result<(i32, f64), ()> {
+            Ok((state + 1, (state + 1) as f64 * self.worse_by))
+        }
+    }
+
+    #[test]
+    fn reheats_when_too_few_moves_are_accepted() {
+        let rng = &mut StdRng::seed_from_u64(0);
+
+        // every move is accepted while it's hot compared to how much worse it is
+        let flowing = Stuck { worse_by: 1e-6 }
+            .optimise((0, 0.), 100, rng, &mut ())
+            .unwrap();
+        assert_eq!(flowing.heat, Heat::default());
+
+        // and barely any are accepted once it's cooled, so it reheats
+        let stuck = Stuck { worse_by: 10. }
+            .optimise((0, 0.), 100, rng, &mut ())
+            .unwrap();
+        assert!(stuck.heat.from > 0.);
+        assert!(stuck.heat.peak < 1.);
     }
 }
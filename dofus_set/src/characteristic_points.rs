@@ -0,0 +1,208 @@
+//! What characteristic points buy and how many a character has to spend.
+//!
+//! The defaults are the rules of the current game version. Every class pays the same for
+//! wisdom and the elements in it, and `CharacteristicRules::for_class` gives the tables of the
+//! classes that differ from the defaults. Other tables, such as the per class tables of older
+//! versions, can be given instead.
+
+use dofus_items::Class;
+use serde::Deserialize;
+
+use crate::dofus_set::OptimiseError;
+
+/// Characteristic points are only earned up to this level, omega levels don't give any
+const LAST_LEVEL_WITH_POINTS: i32 = 200;
+const POINTS_PER_LEVEL: i32 = 5;
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct CharacteristicRules {
+    /// The cost of vitality, wisdom, agility, chance, strength and intelligence, in the same
+    /// order as the characteristic points of a set
+    pub costs: [CostTable; 6],
+    pub points_per_level: i32,
+    pub last_level_with_points: i32,
+}
+
+impl Default for CharacteristicRules {
+    fn default() -> Self {
+        CharacteristicRules {
+            costs: [
+                CostTable::flat(1),
+                CostTable::flat(3),
+                CostTable::elemental(),
+                CostTable::elemental(),
+                CostTable::elemental(),
+                CostTable::elemental(),
+            ],
+            points_per_level: POINTS_PER_LEVEL,
+            last_level_with_points: LAST_LEVEL_WITH_POINTS,
+        }
+    }
+}
+
+impl CharacteristicRules {
+    /// The rules for a class, which are the defaults other than sacriers getting two vitality
+    /// for every point
+    pub fn for_class(class: &Class) -> Self {
+        let mut rules = CharacteristicRules::default();
+        if class.name.eq_ignore_ascii_case("Sacrier") {
+            rules.costs[0] = CostTable(vec![CostTier {
+                from: 0,
+                cost: 1,
+                gain: 2,
+            }]);
+        }
+
+        rules
+    }
+
+    /// Checks that every cost table starts from 0 and rises, with every cost and gain above 0
+    pub fn validate(&self) -> Result<(), OptimiseError> {
+        for (characteristic, CostTable(tiers)) in self.costs.iter().enumerate() {
+            let starts_from_zero = tiers.first().is_none_or(|tier| tier.from == 0);
+            let rises = tiers.windows(2).all(|pair| pair[0].from < pair[1].from);
+            let positive = tiers.iter().all(|tier| tier.cost > 0 && tier.gain > 0);
+
+            if !(starts_from_zero && rises && positive) {
+                return Err(OptimiseError::InvalidCostTable(characteristic));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The characteristic points a character of this level has to spend
+    pub fn points(&self, level: i32) -> i32 {
+        (level.min(self.last_level_with_points) - 1).max(0) * self.points_per_level
+    }
+}
+
+/// How much of a characteristic the points spent in it buy, getting more expensive the more of
+/// it there already is
+#[derive(Clone, Debug, Deserialize)]
+pub struct CostTable(pub Vec<CostTier>);
+
+/// From `from` of a characteristic onwards, every `cost` points buy `gain` of it
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct CostTier {
+    pub from: i32,
+    pub cost: i32,
+    pub gain: i32,
+}
+
+impl CostTable {
+    /// The same cost however much there is
+    pub fn flat(cost: i32) -> Self {
+        CostTable(vec![CostTier {
+            from: 0,
+            cost,
+            gain: 1,
+        }])
+    }
+
+    /// One point each for the first 100, then two, three and four for every 100 after
+    pub fn elemental() -> Self {
+        CostTable(
+            [0, 100, 200, 300]
+                .into_iter()
+                .zip(1..)
+                .map(|(from, cost)| CostTier {
+                    from,
+                    cost,
+                    gain: 1,
+                })
+                .collect(),
+        )
+    }
+
+    /// How much of the characteristic the points buy
+    pub fn gain(&self, points: i32) -> i32 {
+        let mut remaining = points;
+        let mut gained = 0;
+
+        for (i, tier) in self.0.iter().enumerate() {
+            if remaining <= 0 || tier.cost <= 0 {
+                break;
+            }
+
+            let affordable = (remaining / tier.cost).saturating_mul(tier.gain);
+            let room = self
+                .0
+                .get(i + 1)
+                .map_or(i32::MAX, |next| next.from - gained);
+            if affordable <= room {
+                return gained + affordable;
+            }
+
+            // the rest of the tier is bought whole, any points left over go to the next tier
+            let steps = room / tier.gain;
+            gained += steps * tier.gain;
+            remaining -= steps * tier.cost;
+        }
+
+        gained
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cheap_tiers_and_omega_levels() {
+        let cheap = CostTable(vec![
+            CostTier {
+                from: 0,
+                cost: 1,
+                gain: 2,
+            },
+            CostTier {
+                from: 100,
+                cost: 1,
+                gain: 1,
+            },
+        ]);
+        assert_eq!(cheap.gain(49), 98);
+        assert_eq!(cheap.gain(60), 110);
+
+        let rules = CharacteristicRules::default();
+        assert_eq!(rules.points(1), 0);
+        assert_eq!(rules.points(200), 995);
+        assert_eq!(rules.points(240), 995);
+    }
+
+    #[test]
+    fn sacriers_get_more_vitality() {
+        let class = |name| Class { name, spells: &[] };
+
+        let sacrier = CharacteristicRules::for_class(&class("Sacrier"));
+        assert_eq!(sacrier.costs[0].gain(100), 200);
+        assert_eq!(sacrier.costs[4].gain(150), 125);
+        assert!(sacrier.validate().is_ok());
+
+        let iop = CharacteristicRules::for_class(&class("Iop"));
+        assert_eq!(iop.costs[0].gain(100), 100);
+    }
+
+    #[test]
+    fn cost_tables_must_rise_and_cost_something() {
+        assert!(CharacteristicRules::default().validate().is_ok());
+
+        let tier = |from, cost, gain| CostTier { from, cost, gain };
+        let invalid = [
+            vec![tier(0, 0, 1)],
+            vec![tier(0, 1, 0)],
+            vec![tier(10, 1, 1)],
+            vec![tier(0, 1, 1), tier(100, 2, 1), tier(100, 3, 1)],
+        ];
+        for tiers in invalid {
+            let mut rules = CharacteristicRules::default();
+            rules.costs[2] = CostTable(tiers);
+            assert!(matches!(
+                rules.validate(),
+                Err(OptimiseError::InvalidCostTable(2))
+            ));
+        }
+    }
+}
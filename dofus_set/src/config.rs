@@ -1,5 +1,10 @@
-use dofus_items::{ItemIndex, NicheItemIndex};
+use dofus_characteristics::Element;
+use dofus_items::{Class, Damage, Effect, ElementDamage, ItemIndex, NicheItemIndex, Weapon};
+use serde::Deserialize;
 
+use crate::{characteristic_points::CharacteristicRules, dofus_set::OptimiseError};
+
+#[derive(Clone)]
 pub struct Config {
     pub max_level: i32,
     pub weights: [f64; 51],
@@ -13,24 +18,390 @@ pub struct Config {
     pub initial_set: [NicheItemIndex; 16],
     pub changed_item_weight: f64,
     pub damaging_moves: Vec<DamagingMovesOptimisation>,
+    /// How much to value one hit of the weapon equipped in the set
+    pub weapon_hit_weight: f64,
+    /// What the damaging moves and weapon hits are dealing damage to
+    pub target: TargetProfile,
+    pub survivability: Option<SurvivabilityOptimisation>,
+    /// Values the healing from steal damage as well as the damage itself
+    pub count_steal_heals: bool,
     pub consider_characteristics: bool,
+    /// Points from scrolls in vitality, wisdom, agility, chance, strength and intelligence, from
+    /// 0 to 100 each. They're added on top of the characteristic points without changing their
+    /// cost.
+    pub scrolls: [i32; 6],
+    pub characteristic_rules: CharacteristicRules,
+    /// The class of the character, which the spells of damaging moves are looked up in. The
+    /// characteristic rules aren't taken from it here, `CharacteristicRules::for_class` gives them.
+    pub class: Option<&'static Class>,
 }
 
 impl Config {
     pub fn characteristics_point(&self) -> i32 {
-        (self.max_level - 1) * 5
+        self.characteristic_rules.points(self.max_level)
+    }
+
+    /// The damage of a spell at the highest spell level the character has
+    pub fn spell(&self, reference: &SpellReference) -> Result<DamagingMove, OptimiseError> {
+        let class = match &reference.class {
+            Some(name) => {
+                dofus_items::class(name).ok_or_else(|| OptimiseError::UnknownClass(name.clone()))?
+            }
+            None => self
+                .class
+                .ok_or_else(|| OptimiseError::MissingClass(reference.spell.clone()))?,
+        };
+        let spell = class
+            .spell(&reference.spell)
+            .ok_or_else(|| OptimiseError::UnknownSpell(reference.spell.clone()))?;
+
+        let level = reference.level.unwrap_or(self.max_level);
+        let damaging_move = spell
+            .effect(level)
+            .and_then(DamagingMove::from_spell_effect)
+            .ok_or_else(|| OptimiseError::NoSpellDamage {
+                spell: reference.spell.clone(),
+                level,
+            })?;
+
+        Ok(DamagingMove {
+            reach: reference.reach,
+            ..damaging_move
+        })
     }
 }
 
+/// A damaging move that is one of a class's spells, so its damage is looked up rather than
+/// given
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SpellReference {
+    /// The class of the config is used if this isn't given
+    #[serde(default)]
+    pub class: Option<String>,
+    pub spell: String,
+    /// The level of the character casting it, the max level of the config if this isn't given
+    #[serde(default)]
+    pub level: Option<i32>,
+    #[serde(default)]
+    pub reach: Reach,
+}
+
+#[derive(Clone)]
 pub struct DamagingMovesOptimisation {
     pub weight: f64,
     pub damage: DamagingMove,
 }
 
+#[derive(Clone, Debug)]
 pub struct DamagingMove {
-    /// (min + max) / 2
-    pub elemental_damage: [f64; 5],
-    pub crit_elemental_damage: [f64; 5],
+    /// Each line is boosted and resisted on its own, like the lines of a weapon in game
+    pub lines: Vec<MoveLine>,
     pub base_crit_ratio: i32,
     pub modifyable_crit: bool,
+    pub kind: MoveKind,
+    pub reach: Reach,
+    /// Heals rather than deals damage, the line values being the amount healed
+    pub heal: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MoveLine {
+    pub element: Element,
+    pub normal: BaseDamage,
+    pub critical: BaseDamage,
+    /// Heals the caster for half the damage dealt
+    pub steal: bool,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct BaseDamage {
+    pub min: f64,
+    pub max: f64,
+}
+
+impl BaseDamage {
+    pub fn average(self) -> f64 {
+        (self.min + self.max) / 2.
+    }
+}
+
+impl From<ElementDamage> for BaseDamage {
+    fn from(damage: ElementDamage) -> Self {
+        BaseDamage {
+            min: damage.min as f64,
+            max: damage.max as f64,
+        }
+    }
+}
+
+/// The resistances of the target of damaging moves. Per element values are in the order
+/// neutral, air, water, earth, fire like the damage of a `DamagingMove`
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TargetProfile {
+    pub resistance_fixed: [i32; 5],
+    pub resistance_percent: [i32; 5],
+    pub resistance_melee: i32,
+    pub resistance_ranged: i32,
+}
+
+/// Values the effective health of the set against the damage it expects to take
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SurvivabilityOptimisation {
+    pub weight: f64,
+    /// How incoming damage is split between neutral, air, water, earth and fire
+    pub element_mix: [f64; 5],
+    /// The size of a typical incoming hit, which decides how much fixed resistances are worth
+    pub incoming_hit: f64,
+    /// The fraction of incoming damage that is melee, the rest being ranged.
+    /// Melee and ranged resistance are ignored if this isn't given.
+    pub melee_share: Option<f64>,
+    /// Critical resistance is ignored if this isn't given
+    pub critical: Option<IncomingCritical>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IncomingCritical {
+    /// Between 0 and 1
+    pub chance: f64,
+    pub bonus_damage: f64,
+}
+
+/// Decides whether `% Spell Damage` or `% Weapon Damage` applies
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum MoveKind {
+    #[default]
+    Spell,
+    Weapon,
+}
+
+/// Decides whether `% Melee Damage` or `% Ranged Damage` applies
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Reach {
+    Melee,
+    #[default]
+    Ranged,
+}
+
+impl DamagingMove {
+    /// The damage of a spell effect, which is `None` if it doesn't deal any damage. Spells don't
+    /// say how far they reach, so they're taken to be ranged.
+    pub fn from_spell_effect(effect: &Effect) -> Option<Self> {
+        let normal = effect.normal.as_ref()?;
+        let critical = effect.critical.as_ref().unwrap_or(normal);
+
+        // spells have at most one line per element
+        let lines = Element::ALL
+            .into_iter()
+            .zip(elements(normal).into_iter().zip(elements(critical)))
+            .filter(|(_, (normal, critical))| normal.max > 0 || critical.max > 0)
+            .map(|(element, (normal, critical))| MoveLine {
+                element,
+                normal: normal.into(),
+                critical: critical.into(),
+                steal: normal.steal,
+            })
+            .collect();
+
+        Some(DamagingMove {
+            lines,
+            base_crit_ratio: effect.base_crit.unwrap_or(0),
+            // spells without a base critical chance can never critical hit
+            modifyable_crit: effect.base_crit.is_some(),
+            kind: MoveKind::Spell,
+            reach: Reach::Ranged,
+            heal: false,
+        })
+    }
+}
+
+fn elements(damage: &Damage) -> [ElementDamage; 5] {
+    [
+        damage.neutral,
+        damage.air,
+        damage.water,
+        damage.earth,
+        damage.fire,
+    ]
+}
+
+impl From<&Weapon> for DamagingMove {
+    fn from(weapon: &Weapon) -> Self {
+        let bonus = weapon.crit_bonus_damage as f64;
+
+        DamagingMove {
+            lines: weapon
+                .lines
+                .iter()
+                .map(|line| {
+                    let normal = BaseDamage {
+                        min: line.min as f64,
+                        max: line.max as f64,
+                    };
+                    MoveLine {
+                        element: line.element,
+                        normal,
+                        critical: BaseDamage {
+                            min: normal.min + bonus,
+                            max: normal.max + bonus,
+                        },
+                        steal: line.steal,
+                    }
+                })
+                .collect(),
+            base_crit_ratio: weapon.base_crit,
+            // weapons without a base critical chance can never critical hit
+            modifyable_crit: weapon.base_crit > 0,
+            kind: MoveKind::Weapon,
+            reach: if weapon.min_range <= 1 {
+                Reach::Melee
+            } else {
+                Reach::Ranged
+            },
+            heal: false,
+        }
+    }
+}
+
+/// A level 200 character that values nothing, can't change anything and has nothing else
+/// switched on, for tests to change what they need
+#[cfg(test)]
+pub(crate) fn test_config() -> Config {
+    Config {
+        max_level: 200,
+        weights: [0.; 51],
+        targets: [None; 51],
+        changable: Vec::new(),
+        ban_list: Vec::new(),
+        exo_ap: false,
+        exo_mp: false,
+        exo_range: false,
+        multi_element: false,
+        initial_set: [NicheItemIndex::new(None); 16],
+        changed_item_weight: 0.,
+        damaging_moves: Vec::new(),
+        weapon_hit_weight: 0.,
+        target: Default::default(),
+        survivability: None,
+        count_steal_heals: false,
+        consider_characteristics: false,
+        scrolls: [0; 6],
+        characteristic_rules: Default::default(),
+        class: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use dofus_characteristics::Characteristic;
+    use dofus_items::{Spell, ITEMS};
+
+    use super::*;
+    use crate::damage::{damage, Critical, DamageOptions};
+
+    #[test]
+    fn spells_use_the_highest_unlocked_level() {
+        let earth = |min, max| Damage {
+            earth: ElementDamage {
+                min,
+                max,
+                steal: false,
+            },
+            ..Damage::default()
+        };
+        let effects = Box::leak(Box::new([
+            Effect {
+                level: 1,
+                base_crit: Some(5),
+                normal: Some(earth(16, 19)),
+                critical: Some(earth(19, 23)),
+            },
+            Effect {
+                level: 100,
+                base_crit: None,
+                normal: Some(earth(20, 24)),
+                critical: None,
+            },
+        ]));
+        let spells = Box::leak(Box::new([Spell {
+            name: "Pressure",
+            description: "",
+            image_url: "",
+            effects,
+        }]));
+        let class = Box::leak(Box::new(Class {
+            name: "Iop",
+            spells,
+        }));
+
+        let mut config = Config {
+            max_level: 99,
+            class: Some(class),
+            ..test_config()
+        };
+
+        let reference = |spell: &str| SpellReference {
+            class: None,
+            spell: spell.to_owned(),
+            level: None,
+            reach: Reach::Ranged,
+        };
+
+        let earth_line = |min, max, critical_min, critical_max| MoveLine {
+            element: Element::Earth,
+            normal: BaseDamage { min, max },
+            critical: BaseDamage {
+                min: critical_min,
+                max: critical_max,
+            },
+            steal: false,
+        };
+
+        let pressure = config.spell(&reference("pressure")).unwrap();
+        assert_eq!(pressure.lines, [earth_line(16., 19., 19., 23.)]);
+        assert!(pressure.modifyable_crit);
+
+        config.max_level = 100;
+        let pressure = config.spell(&reference("Pressure")).unwrap();
+        assert_eq!(pressure.lines, [earth_line(20., 24., 20., 24.)]);
+        assert!(!pressure.modifyable_crit);
+
+        assert!(matches!(
+            config.spell(&reference("Bond")),
+            Err(OptimiseError::UnknownSpell(_))
+        ));
+
+        config.max_level = 1;
+        let unlocked = config.spell(&SpellReference {
+            level: Some(100),
+            ..reference("Pressure")
+        });
+        assert_eq!(unlocked.unwrap().lines, [earth_line(20., 24., 20., 24.)]);
+    }
+
+    #[test]
+    fn weapons_only_steal_with_their_steal_lines() {
+        let (_, hammer) = ITEMS
+            .iter()
+            .find(|(_, item)| item.name == "Crick Hammer")
+            .unwrap();
+        let hit = DamagingMove::from(hammer.weapon.as_ref().unwrap());
+
+        let damage = damage(
+            &Characteristic::new(),
+            &hit,
+            &DamageOptions {
+                critical: Critical::Normal,
+                target: None,
+            },
+        );
+        let (total, stolen) = (damage.total(), damage.stolen());
+        assert_eq!((total.min, total.max), (29., 48.));
+        assert_eq!((stolen.min, stolen.max), (2., 3.));
+    }
 }
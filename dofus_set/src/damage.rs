@@ -0,0 +1,465 @@
+//! The damage a `DamagingMove` deals for a given set of characteristics.
+//!
+//! Normal and critical hits are rounded down at each step like they are in game. The average
+//! mixes the two by the chance of a critical hit without rounding, which is what the optimiser
+//! uses so that every point of a stat makes a difference.
+
+use dofus_characteristics::{Characteristic, Element, Stat};
+use serde::Serialize;
+
+use crate::config::{DamagingMove, MoveKind, Reach, TargetProfile};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Critical {
+    Normal,
+    Critical,
+    /// The expected damage given the chance of a critical hit
+    Average,
+}
+
+pub struct DamageOptions<'a> {
+    pub critical: Critical,
+    /// The resistances of whatever is being hit, nothing is resisted if this isn't given
+    pub target: Option<&'a TargetProfile>,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+pub struct DamageRoll {
+    pub min: f64,
+    pub max: f64,
+    pub average: f64,
+}
+
+impl DamageRoll {
+    fn new(min: f64, max: f64) -> Self {
+        DamageRoll {
+            min,
+            max,
+            average: (min + max) / 2.,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct LineDamage {
+    pub element: Element,
+    pub roll: DamageRoll,
+    pub steal: bool,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct MoveDamage {
+    /// The damage, or healing if the move is a heal, of each line of the move
+    pub lines: Vec<LineDamage>,
+}
+
+impl MoveDamage {
+    /// The damage of all the lines in an element
+    pub fn element(&self, element: Element) -> DamageRoll {
+        sum(self
+            .lines
+            .iter()
+            .filter(|line| line.element == element)
+            .map(|line| line.roll))
+    }
+
+    pub fn total(&self) -> DamageRoll {
+        sum(self.lines.iter().map(|line| line.roll))
+    }
+
+    /// The healing the caster gets from the lines that steal
+    pub fn stolen(&self) -> DamageRoll {
+        sum(self
+            .lines
+            .iter()
+            .filter(|line| line.steal)
+            .map(|line| DamageRoll::new(line.roll.min / 2., line.roll.max / 2.)))
+    }
+}
+
+fn sum(rolls: impl Iterator<Item = DamageRoll>) -> DamageRoll {
+    rolls.fold(DamageRoll::default(), |total, roll| {
+        DamageRoll::new(total.min + roll.min, total.max + roll.max)
+    })
+}
+
+/// The chance between 0 and 1 that the move is a critical hit
+pub fn critical_chance(stats: &Characteristic, damaging_move: &DamagingMove) -> f64 {
+    let critical = if damaging_move.modifyable_crit {
+        (damaging_move.base_crit_ratio + stats[Stat::Critical]).clamp(0, 100)
+    } else {
+        damaging_move.base_crit_ratio
+    };
+
+    critical as f64 / 100.
+}
+
+pub fn damage(
+    stats: &Characteristic,
+    damaging_move: &DamagingMove,
+    options: &DamageOptions,
+) -> MoveDamage {
+    let critical_chance = critical_chance(stats, damaging_move);
+
+    let lines = damaging_move
+        .lines
+        .iter()
+        .map(|line| {
+            let hit = |base: f64, critical: bool, rounded: bool| {
+                line_damage(
+                    stats,
+                    damaging_move,
+                    options.target,
+                    line.element,
+                    base,
+                    critical,
+                    rounded,
+                )
+            };
+
+            let roll = |normal: f64, critical: f64| match options.critical {
+                Critical::Normal => hit(normal, false, true),
+                Critical::Critical => hit(critical, true, true),
+                Critical::Average => {
+                    hit(normal, false, false) * (1. - critical_chance)
+                        + hit(critical, true, false) * critical_chance
+                }
+            };
+
+            LineDamage {
+                element: line.element,
+                roll: DamageRoll::new(
+                    roll(line.normal.min, line.critical.min),
+                    roll(line.normal.max, line.critical.max),
+                ),
+                steal: line.steal,
+            }
+        })
+        .collect();
+
+    MoveDamage { lines }
+}
+
+fn line_damage(
+    stats: &Characteristic,
+    damaging_move: &DamagingMove,
+    target: Option<&TargetProfile>,
+    element: Element,
+    base: f64,
+    critical: bool,
+    rounded: bool,
+) -> f64 {
+    if base == 0. {
+        return 0.;
+    }
+
+    let round = |x: f64| if rounded { x.floor() } else { x };
+    let power = stats[Stat::Power];
+
+    // heals scale with intelligence whatever element they're in, and nothing reduces them
+    if damaging_move.heal {
+        let multiplier = (100 + stats[Stat::Intelligence] + power).max(0) as f64 / 100.;
+        return round(base * multiplier) + stats[Stat::Heal] as f64;
+    }
+
+    let multiplier = (100 + stats[element.characteristic()] + power).max(0) as f64 / 100.;
+    let mut damage =
+        round(base * multiplier) + (stats[Stat::Damage] + stats[element.damage()]) as f64;
+    if critical {
+        damage += stats[Stat::DamageCritical] as f64;
+    }
+
+    if let Some(target) = target {
+        damage = round(
+            (damage - target.resistance_fixed[element as usize] as f64).max(0.)
+                * resistance_multiplier(target.resistance_percent[element as usize]),
+        );
+    }
+
+    let kind_damage = match damaging_move.kind {
+        MoveKind::Spell => stats[Stat::DamageSpell],
+        MoveKind::Weapon => stats[Stat::DamageWeapon],
+    };
+    let (reach_damage, reach_resistance) = match damaging_move.reach {
+        Reach::Melee => (
+            stats[Stat::DamageMelee],
+            target.map_or(0, |target| target.resistance_melee),
+        ),
+        Reach::Ranged => (
+            stats[Stat::DamageRange],
+            target.map_or(0, |target| target.resistance_ranged),
+        ),
+    };
+
+    // the final damage bonuses and resistances multiply everything else
+    round(
+        damage
+            * (1. + kind_damage as f64 / 100.)
+            * (1. + reach_damage as f64 / 100.)
+            * resistance_multiplier(reach_resistance),
+    )
+    .max(0.)
+}
+
+pub(crate) fn resistance_multiplier(resistance_percent: i32) -> f64 {
+    (1. - resistance_percent as f64 / 100.).max(0.)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{BaseDamage, MoveLine};
+
+    fn line(element: Element, min: f64, max: f64) -> MoveLine {
+        MoveLine {
+            element,
+            normal: BaseDamage { min, max },
+            critical: BaseDamage { min, max },
+            steal: false,
+        }
+    }
+
+    fn single_element_move(element: Element, min: f64, max: f64) -> DamagingMove {
+        DamagingMove {
+            lines: vec![line(element, min, max)],
+            base_crit_ratio: 0,
+            modifyable_crit: false,
+            kind: MoveKind::Spell,
+            reach: Reach::Ranged,
+            heal: false,
+        }
+    }
+
+    fn normal_hit(stats: &Characteristic, damaging_move: &DamagingMove) -> MoveDamage {
+        damage(
+            stats,
+            damaging_move,
+            &DamageOptions {
+                critical: Critical::Normal,
+                target: None,
+            },
+        )
+    }
+
+    #[test]
+    fn neutral_damage_scales_with_strength() {
+        let mut stats = Characteristic::new();
+        stats[Stat::Strength] = 100;
+
+        let damage = normal_hit(&stats, &single_element_move(Element::Neutral, 10., 12.));
+        assert_eq!(damage.element(Element::Neutral), DamageRoll::new(20., 24.));
+
+        stats[Stat::Strength] = 0;
+        stats[Stat::Agility] = 100;
+        let damage = normal_hit(&stats, &single_element_move(Element::Neutral, 10., 12.));
+        assert_eq!(damage.element(Element::Neutral), DamageRoll::new(10., 12.));
+    }
+
+    #[test]
+    fn damage_with_power_and_fixed_damage() {
+        let mut stats = Characteristic::new();
+        stats[Stat::Strength] = 500;
+        stats[Stat::Power] = 50;
+        stats[Stat::DamageEarth] = 20;
+        stats[Stat::DamageFire] = 1000;
+
+        let damage = normal_hit(&stats, &single_element_move(Element::Earth, 26., 30.));
+        assert_eq!(damage.total(), DamageRoll::new(189., 215.));
+    }
+
+    #[test]
+    fn critical_hit_adds_critical_damage() {
+        let mut stats = Characteristic::new();
+        stats[Stat::Intelligence] = 100;
+        stats[Stat::DamageCritical] = 15;
+
+        let mut damaging_move = single_element_move(Element::Fire, 10., 13.);
+        damaging_move.lines[0].critical = BaseDamage { min: 13., max: 16. };
+        damaging_move.base_crit_ratio = 25;
+
+        let critical = damage(
+            &stats,
+            &damaging_move,
+            &DamageOptions {
+                critical: Critical::Critical,
+                target: None,
+            },
+        );
+        assert_eq!(critical.total(), DamageRoll::new(41., 47.));
+
+        let average = damage(
+            &stats,
+            &damaging_move,
+            &DamageOptions {
+                critical: Critical::Average,
+                target: None,
+            },
+        );
+        assert_eq!(
+            average.total(),
+            DamageRoll::new(20. * 0.75 + 41. * 0.25, 26. * 0.75 + 47. * 0.25)
+        );
+    }
+
+    #[test]
+    fn every_line_gets_the_fixed_damage() {
+        let mut stats = Characteristic::new();
+        stats[Stat::Strength] = 100;
+        stats[Stat::Damage] = 10;
+        stats[Stat::DamageNeutral] = 5;
+
+        let mut damaging_move = single_element_move(Element::Neutral, 7., 12.);
+        damaging_move.lines.push(line(Element::Neutral, 7., 12.));
+
+        let damage = normal_hit(&stats, &damaging_move);
+        assert_eq!(damage.lines.len(), 2);
+        assert_eq!(damage.element(Element::Neutral), DamageRoll::new(58., 78.));
+    }
+
+    #[test]
+    fn resistances_and_final_damage() {
+        let mut stats = Characteristic::new();
+        stats[Stat::DamageSpell] = 10;
+        stats[Stat::DamageRange] = 10;
+        stats[Stat::DamageMelee] = 1000;
+
+        let target = TargetProfile {
+            resistance_fixed: [0, 20, 0, 0, 0],
+            resistance_percent: [0, 25, 0, 0, 0],
+            resistance_melee: 0,
+            resistance_ranged: 10,
+        };
+
+        let damage = damage(
+            &stats,
+            &single_element_move(Element::Air, 100., 100.),
+            &DamageOptions {
+                critical: Critical::Normal,
+                target: Some(&target),
+            },
+        );
+        // (100 - 20) * 75% = 60, then * 110% * 110% * 90% = 65.34
+        assert_eq!(damage.total(), DamageRoll::new(65., 65.));
+    }
+
+    /// Hits worked through by hand, a rounding at a time, for typical level 200 characters. These
+    /// check the formula as it's written here, not against hits observed in game, so hits seen in
+    /// game should be added alongside them along with where they were seen.
+    #[test]
+    fn hand_worked_hits() {
+        struct Hit {
+            stats: &'static [(Stat, i32)],
+            damaging_move: DamagingMove,
+            critical: Critical,
+            target: TargetProfile,
+            expected: DamageRoll,
+        }
+
+        let mut melee_earth = single_element_move(Element::Earth, 26., 30.);
+        melee_earth.reach = Reach::Melee;
+
+        let mut ranged_fire = single_element_move(Element::Fire, 20., 24.);
+        ranged_fire.lines[0].critical = BaseDamage { min: 24., max: 29. };
+
+        let mut air_and_water = single_element_move(Element::Air, 10., 12.);
+        air_and_water.lines.push(line(Element::Water, 8., 9.));
+
+        let hits = [
+            // 26 * 1050% = 273, + 70 = 343, - 20 = 323, * 70% = 226, * 112% * 115% = 291
+            // 30 * 1050% = 315, + 70 = 385, - 20 = 365, * 70% = 255, * 112% * 115% = 328
+            Hit {
+                stats: &[
+                    (Stat::Strength, 850),
+                    (Stat::Power, 100),
+                    (Stat::Damage, 40),
+                    (Stat::DamageEarth, 30),
+                    (Stat::DamageSpell, 12),
+                    (Stat::DamageMelee, 15),
+                    (Stat::DamageRange, 30),
+                ],
+                damaging_move: melee_earth,
+                critical: Critical::Normal,
+                target: TargetProfile {
+                    resistance_fixed: [0, 0, 0, 20, 0],
+                    resistance_percent: [0, 0, 0, 30, 0],
+                    resistance_melee: 0,
+                    resistance_ranged: 50,
+                },
+                expected: DamageRoll::new(291., 328.),
+            },
+            // 24 * 700% = 168, + 10 + 25 critical = 203, * 90% = 182, * 110% * 95% = 190
+            // 29 * 700% = 203, + 10 + 25 critical = 238, * 90% = 214, * 110% * 95% = 223
+            Hit {
+                stats: &[
+                    (Stat::Intelligence, 600),
+                    (Stat::Damage, 10),
+                    (Stat::DamageCritical, 25),
+                    (Stat::DamageRange, 10),
+                ],
+                damaging_move: ranged_fire,
+                critical: Critical::Critical,
+                target: TargetProfile {
+                    resistance_fixed: [0; 5],
+                    resistance_percent: [0, 0, 0, 0, 10],
+                    resistance_melee: 0,
+                    resistance_ranged: 5,
+                },
+                expected: DamageRoll::new(190., 223.),
+            },
+            // air 10 * 400% = 40 and 12 * 400% = 48, water 8 * 250% = 20 and 9 * 250% = 22,
+            // with the water hit losing 5 to resistance and then 20%
+            Hit {
+                stats: &[(Stat::Agility, 300), (Stat::Chance, 150)],
+                damaging_move: air_and_water,
+                critical: Critical::Normal,
+                target: TargetProfile {
+                    resistance_fixed: [0, 0, 5, 0, 0],
+                    resistance_percent: [0, 0, 20, 0, 0],
+                    resistance_melee: 0,
+                    resistance_ranged: 0,
+                },
+                expected: DamageRoll::new(40. + 12., 48. + 13.),
+            },
+        ];
+
+        for hit in hits {
+            let mut stats = Characteristic::new();
+            for &(stat, value) in hit.stats {
+                stats[stat] = value;
+            }
+
+            let damage = damage(
+                &stats,
+                &hit.damaging_move,
+                &DamageOptions {
+                    critical: hit.critical,
+                    target: Some(&hit.target),
+                },
+            );
+            assert_eq!(damage.total(), hit.expected);
+        }
+    }
+
+    #[test]
+    fn heals_scale_with_intelligence() {
+        let mut stats = Characteristic::new();
+        stats[Stat::Intelligence] = 200;
+        stats[Stat::Strength] = 1000;
+        stats[Stat::Heal] = 10;
+
+        let mut heal = single_element_move(Element::Water, 20., 25.);
+        heal.heal = true;
+
+        let healed = normal_hit(&stats, &heal);
+        assert_eq!(healed.total(), DamageRoll::new(70., 85.));
+    }
+
+    #[test]
+    fn steal_heals_for_half_the_damage() {
+        let mut damaging_move = single_element_move(Element::Fire, 20., 30.);
+        damaging_move.lines[0].steal = true;
+
+        let damage = normal_hit(&Characteristic::new(), &damaging_move);
+        assert_eq!(damage.stolen(), DamageRoll::new(10., 15.));
+    }
+}
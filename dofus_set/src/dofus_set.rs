@@ -1,11 +1,26 @@
-use std::ops::Index;
-
-use crate::{anneal, config, config::Config};
-
-use dofus_characteristics::{stat_is_element, Characteristic, Stat, STAT_ELEMENT};
-use dofus_items::{Item, ItemIndex, ItemType, Items, NicheItemIndex, SetIndex};
-use rand::{prelude::Rng, seq::SliceRandom};
-use serde::Serialize;
+use std::{
+    collections::BTreeMap,
+    ops::{ControlFlow, Index},
+};
+
+use crate::{
+    anneal::{self, Anneal, Annealed, CoolingSchedule, Heat, Observer},
+    config,
+    config::{Config, DamagingMove, SurvivabilityOptimisation},
+    damage::{self, resistance_multiplier, Critical, DamageOptions},
+};
+
+use dofus_characteristics::{
+    stat_is_element, Characteristic, Element, Restriction, Stat, STAT_ELEMENT,
+};
+use dofus_items::{Item, ItemIndex, ItemType, Items, NicheItemIndex, SetIndex, Weapon};
+use rand::{
+    prelude::Rng,
+    rngs::StdRng,
+    seq::{IteratorRandom, SliceRandom},
+    SeedableRng,
+};
+use serde::{Deserialize, Serialize};
 
 pub fn slot_index_to_item_type(index: usize) -> ItemType {
     match index {
@@ -23,48 +38,81 @@ pub fn slot_index_to_item_type(index: usize) -> ItemType {
     }
 }
 
+pub(crate) const WEAPON_SLOT: usize = 7;
+const RING_SLOTS: [usize; 2] = [3, 4];
+
+/// The characteristics that points are spent in and scrolls give, in the order of the
+/// characteristic points, `Config::scrolls` and the cost tables
+const CHARACTERISTICS: [Stat; 6] = [
+    Stat::Vitality,
+    Stat::Wisdom,
+    Stat::Agility,
+    Stat::Chance,
+    Stat::Strength,
+    Stat::Intelligence,
+];
+
 const MAX_AP: i32 = 12;
 const MAX_MP: i32 = 6;
 const MAX_RANGE: i32 = 6;
+/// The most of each characteristic scrolls can give
+const MAX_SCROLL: i32 = 100;
+
+/// The number of random moves looked at to estimate an initial temperature
+const TEMPERATURE_SAMPLES: usize = 200;
+/// The chance of accepting an average move that makes the set worse at an estimated initial temperature
+const INITIAL_ACCEPTANCE: f64 = 0.8;
+
+/// The numbers of characteristic points moved at once when polishing, larger steps are needed to
+/// get past characteristics where a single point doesn't change anything
+const POINT_STEPS: [i32; 5] = [1, 5, 20, 100, 300];
+
+/// The places characteristic points can be moved between, each characteristic and then the points
+/// that haven't been spent
+const POINT_POOLS: usize = CHARACTERISTICS.len() + 1;
+
+/// The most times `Optimiser::allocate_points` refines its split when characteristics affect
+/// each other
+const ALLOCATION_SWEEPS: usize = 16;
+
+/// The number of slots in a set
+const SLOTS: usize = 16;
 
 #[derive(Clone, Debug)]
 pub struct State {
-    set: [NicheItemIndex; 16],
+    set: [NicheItemIndex; SLOTS],
     characteristic_points: [i32; 6],
     cached_totals: Characteristic,
+    /// The number of equipped items from each set. An item is in at most one set, so there's
+    /// never more sets than slots.
+    cached_set_counts: heapless::Vec<(SetIndex, i32), SLOTS>,
 }
 
 impl State {
-    fn new_from_initial_equipment(
-        equipment: [Option<ItemIndex>; 16],
+    pub(crate) fn new_from_initial_equipment(
+        equipment: [Option<ItemIndex>; SLOTS],
         items: &Items,
     ) -> Result<State, OptimiseError> {
-        let mut set = [None; 16];
+        let mut state = State {
+            set: [NicheItemIndex::new(None); SLOTS],
+            characteristic_points: [0; 6],
+            cached_totals: Characteristic::new(),
+            cached_set_counts: heapless::Vec::new(),
+        };
+
         for (index, equipment) in equipment.iter().enumerate() {
             if let Some(equipment) = equipment {
                 if !items[slot_index_to_item_type(index)].contains(equipment) {
                     return Err(OptimiseError::InvalidItem {
-                        item: items[*equipment].name.to_owned(),
+                        item: items[*equipment].name.to_string(),
                         attempted_slot: slot_index_to_item_type(index),
                     });
                 }
-                set[index] = Some(*equipment);
+                state.set_item(index, Some(*equipment), items);
             }
         }
 
-        let niche_optimised = set.map(NicheItemIndex::new);
-
-        let state = State {
-            set: niche_optimised,
-            characteristic_points: [0; 6],
-            cached_totals: Characteristic::new(),
-        };
-        let totals = state.item_stat_from_nothing(items);
-        Ok(State {
-            set: niche_optimised,
-            characteristic_points: [0; 6],
-            cached_totals: totals,
-        })
+        Ok(state)
     }
 }
 
@@ -73,44 +121,84 @@ pub struct SetBonus<'a> {
     pub bonus: &'a Characteristic,
     pub number_of_items: i32,
 }
-type SetBonusList<'a> = heapless::Vec<SetBonus<'a>, MAX_SETS>;
-
-const MAX_SETS: usize = 12;
+type SetBonusList<'a> = heapless::Vec<SetBonus<'a>, SLOTS>;
 
 impl State {
     pub fn set(&'_ self) -> impl std::iter::Iterator<Item = Option<ItemIndex>> + '_ {
         self.set.iter().map(|x| x.get())
     }
 
+    /// The number of slots with different items. Rings and dofus can be worn in any of their
+    /// slots, so they only count as different if the other set doesn't wear them at all.
+    pub fn distance(&self, other: &State) -> usize {
+        let mut distance = 0;
+        let mut start = 0;
+        while start < SLOTS {
+            let item_type = slot_index_to_item_type(start);
+            let end = (start..SLOTS)
+                .find(|&slot| slot_index_to_item_type(slot) != item_type)
+                .unwrap_or(SLOTS);
+
+            let mut unmatched = other.set[start..end].to_vec();
+            for item in &self.set[start..end] {
+                match unmatched.iter().position(|other| other == item) {
+                    Some(matched) => {
+                        unmatched.swap_remove(matched);
+                    }
+                    None => distance += 1,
+                }
+            }
+
+            start = end;
+        }
+        distance
+    }
+
     pub fn points(&self) -> &[i32] {
         &self.characteristic_points
     }
 
-    pub fn sets<'a>(&self, items: &'a Items) -> SetBonusList<'a> {
-        let mut sets_linear_map: heapless::Vec<(SetIndex, i32), MAX_SETS> = heapless::Vec::new();
+    /// The points in one of the `POINT_POOLS`
+    fn points_in_pool(&self, config: &Config, pool: usize) -> i32 {
+        self.characteristic_points
+            .get(pool)
+            .copied()
+            .unwrap_or_else(|| (config.characteristics_point() - self.spent_points()).max(0))
+    }
 
-        for item in self.items(items) {
-            if let Some(set_id) = item.set_id {
-                if let Some((_, count)) = sets_linear_map
-                    .iter_mut()
-                    .find(|(set_index, _)| *set_index == set_id)
-                {
-                    *count += 1;
-                } else {
-                    sets_linear_map
-                        .push((set_id, 1))
-                        .expect("Too many different sets");
-                }
-            }
+    /// Moves `step` points from one of the `POINT_POOLS` to another, so no more than the budget
+    /// is ever spent. Nothing moves if there aren't that many points to move.
+    fn move_points(&mut self, config: &Config, from: usize, to: usize, step: i32) -> bool {
+        if step <= 0 || step > self.points_in_pool(config, from) {
+            return false;
         }
 
-        sets_linear_map
-            .into_iter()
-            .filter_map(move |(set, number_of_items)| {
+        if let Some(from) = self.characteristic_points.get_mut(from) {
+            *from -= step;
+        }
+        if let Some(to) = self.characteristic_points.get_mut(to) {
+            *to += step;
+        }
+        true
+    }
+
+    fn spent_points(&self) -> i32 {
+        self.characteristic_points.iter().sum()
+    }
+
+    /// The energy of spending more characteristic points than the budget
+    fn overspending_energy(&self, config: &Config) -> f64 {
+        ((self.spent_points() - config.characteristics_point()).max(0) * 100) as f64
+    }
+
+    pub fn sets<'a>(&self, items: &'a Items) -> SetBonusList<'a> {
+        self.cached_set_counts
+            .iter()
+            .filter_map(move |&(set, number_of_items)| {
                 let set = &items[set];
 
                 set.get(number_of_items as usize).map(|bonus| SetBonus {
-                    name: set.name,
+                    name: &set.name,
                     bonus,
                     number_of_items,
                 })
@@ -171,12 +259,7 @@ impl State {
             }
         }
 
-        let total_used_points: i32 = self.characteristic_points.iter().copied().sum();
-        let total_points = config.characteristics_point();
-
-        let over_usage = (total_used_points - total_points).max(0);
-
-        violation_energy += (over_usage * 100) as f64;
+        violation_energy += self.overspending_energy(config);
 
         violation_energy
     }
@@ -184,97 +267,26 @@ impl State {
     pub fn energy(&self, config: &config::Config, items: &Items, sets: &SetBonusList) -> f64 {
         let stats = self.stats(config, sets);
         // need to take the negative due to being a minimiser
-        let energy_non_element = stats
-            .iter()
-            .zip(config.weights.iter())
-            .zip(config.targets.iter())
-            .enumerate()
-            .filter(|(x, _)| !stat_is_element(*x))
-            .map(|(_, x)| x)
-            .map(|((&stat, &weight), &target)| {
-                let stat = target.map_or_else(|| stat, |target| std::cmp::min(target, stat));
-                stat as f64 * weight
-            })
-            .sum::<f64>();
-
-        let difference_energy = config
-            .initial_set
-            .iter()
-            .zip(self.set.iter())
-            .filter(|(a, b)| a != b)
-            .count() as f64
-            * config.changed_item_weight;
-
-        let damage_energy = config
-            .damaging_moves
-            .iter()
-            .map(|x| {
-                let critical = if x.damage.modifyable_crit {
-                    (x.damage.base_crit_ratio + stats[Stat::Critical]).clamp(0, 100) as f64
-                } else {
-                    x.damage.base_crit_ratio as f64
-                };
-                let ratio = critical / 100.;
-                let damage_stats = [
-                    (Stat::Strength, Stat::DamageNeutral),
-                    (Stat::Agility, Stat::DamageAir),
-                    (Stat::Chance, Stat::DamageWater),
-                    (Stat::Strength, Stat::DamageEarth),
-                    (Stat::Intelligence, Stat::DamageFire),
-                ];
-
-                let critical_damage = stats[Stat::DamageCritical];
-                let power = stats[Stat::Power];
-                let damage = stats[Stat::Damage];
-                x.damage
-                    .elemental_damage
-                    .into_iter()
-                    .zip(x.damage.crit_elemental_damage)
-                    .zip(damage_stats)
-                    .map(|((b, c), (stat_power, stat_damage))| {
-                        let stat_power = stats[stat_power];
-                        let average_base_damage = b * (1. - ratio) + c * ratio;
-
-                        if average_base_damage != 0. {
-                            average_base_damage * (1. + ((stat_power + power) as f64) / 100.)
-                                + (damage + stats[stat_damage]) as f64
-                                + ratio * critical_damage as f64
-                        } else {
-                            0.
-                        }
-                    })
-                    .sum::<f64>()
-                    * x.weight
-            })
-            .sum::<f64>();
+        -stat_value(config, &stats)
+            - combat_value(config, &stats, self.weapon(items))
+            - self.changed_items(config, 0..self.set.len()) as f64 * config.changed_item_weight
+            + self.restriction_energy(config, &stats, items, sets)
+    }
 
-        let element_iter = STAT_ELEMENT
-            .iter()
-            .map(|&x| {
-                (
-                    stats[x],
-                    config.weights[x as usize],
-                    config.targets[x as usize],
-                )
-            })
-            .filter(|(_, weight, _)| *weight > 0.)
-            .map(|(stat, weight, target)| {
-                let stat = target.map_or_else(|| stat, |target| std::cmp::min(target, stat));
-                stat as f64 * weight
-            });
-        let energy_element = if config.multi_element {
-            let e = element_iter.fold(f64::NAN, f64::min);
-            if e.is_nan() {
-                0.
-            } else {
-                e
-            }
-        } else {
-            element_iter.sum()
-        };
+    /// The number of the given slots that hold a different item to the initial set
+    pub(crate) fn changed_items(
+        &self,
+        config: &Config,
+        slots: impl IntoIterator<Item = usize>,
+    ) -> usize {
+        slots
+            .into_iter()
+            .filter(|&slot| config.initial_set[slot] != self.set[slot])
+            .count()
+    }
 
-        -energy_non_element - energy_element - difference_energy - damage_energy
-            + self.restriction_energy(config, &stats, items, sets)
+    pub(crate) fn weapon<'a>(&self, items: &'a Items) -> Option<&'a Weapon> {
+        self.set[WEAPON_SLOT].get().and_then(|x| items.weapon(x))
     }
 
     fn items<'a>(
@@ -286,42 +298,86 @@ impl State {
             .filter_map(move |item_id| item_id.get().map(|item_id| &items[item_id]))
     }
 
-    fn item_stat_from_nothing(&self, items: &Items) -> Characteristic {
-        let mut stat = Characteristic::new();
-
-        for item in self.items(items) {
-            stat += &item.stats;
-        }
+    pub(crate) fn item(&self, slot: usize) -> Option<ItemIndex> {
+        self.set[slot].get()
+    }
 
-        stat
+    /// The total stats of the equipped items
+    pub(crate) fn item_totals(&self) -> &Characteristic {
+        &self.cached_totals
     }
 
     fn remove_item(&mut self, item: &Item) {
         self.cached_totals -= &item.stats;
+
+        if let Some(set) = item.set_id {
+            if let Some(position) = self
+                .cached_set_counts
+                .iter()
+                .position(|&(other, _)| other == set)
+            {
+                self.cached_set_counts[position].1 -= 1;
+                if self.cached_set_counts[position].1 == 0 {
+                    self.cached_set_counts.swap_remove(position);
+                }
+            }
+        }
     }
 
     fn add_item(&mut self, item: &Item) {
         self.cached_totals += &item.stats;
+
+        if let Some(set) = item.set_id {
+            match self
+                .cached_set_counts
+                .iter_mut()
+                .find(|(other, _)| *other == set)
+            {
+                Some((_, count)) => *count += 1,
+                // there's at most one entry per equipped item so this always fits, but if it
+                // somehow doesn't the set goes uncounted rather than ending the optimisation
+                None => {
+                    let _ = self.cached_set_counts.push((set, 1));
+                }
+            }
+        }
     }
 
-    pub fn stats(
-        &self,
-        config: &config::Config,
-        sets: &heapless::Vec<SetBonus<'_>, MAX_SETS>,
-    ) -> Characteristic {
+    pub(crate) fn set_item(&mut self, slot: usize, item: Option<ItemIndex>, items: &Items) {
+        if let Some(old_item) = self.set[slot].get() {
+            self.remove_item(&items[old_item]);
+        }
+        if let Some(item) = item {
+            self.add_item(&items[item]);
+        }
+
+        self.set[slot] = NicheItemIndex::new(item);
+    }
+
+    pub fn stats(&self, config: &config::Config, sets: &SetBonusList) -> Characteristic {
         let mut stat = self.cached_totals.clone();
 
         for set_bonus in sets {
             stat += set_bonus.bonus;
         }
 
-        stat[Stat::Vitality] += self.characteristic_points[0];
-        stat[Stat::Wisdom] += self.characteristic_points[1] / 3;
+        self.stats_from_totals(config, stat)
+    }
 
-        stat[Stat::Agility] += calculate_points_for_stat(self.characteristic_points[2]);
-        stat[Stat::Chance] += calculate_points_for_stat(self.characteristic_points[3]);
-        stat[Stat::Strength] += calculate_points_for_stat(self.characteristic_points[4]);
-        stat[Stat::Intelligence] += calculate_points_for_stat(self.characteristic_points[5]);
+    /// Adds the characteristic points and base stats to the total of the items and set bonuses
+    pub(crate) fn stats_from_totals(
+        &self,
+        config: &config::Config,
+        mut stat: Characteristic,
+    ) -> Characteristic {
+        for (((&characteristic, scroll), &points), cost) in CHARACTERISTICS
+            .iter()
+            .zip(config.scrolls)
+            .zip(&self.characteristic_points)
+            .zip(&config.characteristic_rules.costs)
+        {
+            stat[characteristic] += scroll.clamp(0, MAX_SCROLL) + cost.gain(points);
+        }
 
         stat[Stat::AP] = std::cmp::min(
             stat[Stat::AP] + level_initial_ap(config.max_level) + config.exo_ap as i32,
@@ -341,11 +397,170 @@ impl State {
     }
 }
 
-fn calculate_points_for_stat(points_in: i32) -> i32 {
-    points_in.min(100)
-        + (points_in - 100).clamp(0, 200) / 2
-        + (points_in - 300).clamp(0, 300) / 3
-        + (points_in - 600).max(0) / 4
+/// How much the weighted stats are worth, taking targets and multi element into account
+pub(crate) fn stat_value(config: &Config, stats: &Characteristic) -> f64 {
+    let non_element = stats
+        .iter()
+        .zip(config.weights.iter())
+        .zip(config.targets.iter())
+        .enumerate()
+        .filter(|(x, _)| !stat_is_element(*x))
+        .map(|(_, x)| x)
+        .map(|((&stat, &weight), &target)| {
+            let stat = target.map_or_else(|| stat, |target| std::cmp::min(target, stat));
+            stat as f64 * weight
+        })
+        .sum::<f64>();
+
+    let element_iter = STAT_ELEMENT
+        .iter()
+        .map(|&x| {
+            (
+                stats[x],
+                config.weights[x as usize],
+                config.targets[x as usize],
+            )
+        })
+        .filter(|(_, weight, _)| *weight > 0.)
+        .map(|(stat, weight, target)| {
+            let stat = target.map_or_else(|| stat, |target| std::cmp::min(target, stat));
+            stat as f64 * weight
+        });
+    let element = if config.multi_element {
+        let e = element_iter.fold(f64::NAN, f64::min);
+        if e.is_nan() {
+            0.
+        } else {
+            e
+        }
+    } else {
+        element_iter.sum()
+    };
+
+    non_element + element
+}
+
+/// How much the damaging moves, weapon hits and survivability are worth
+pub(crate) fn combat_value(
+    config: &Config,
+    stats: &Characteristic,
+    weapon: Option<&Weapon>,
+) -> f64 {
+    let damage = config
+        .damaging_moves
+        .iter()
+        .map(|x| move_damage(stats, &x.damage, config) * x.weight)
+        .sum::<f64>()
+        + weapon.map_or(0., |weapon| {
+            move_damage(stats, &DamagingMove::from(weapon), config) * config.weapon_hit_weight
+        });
+
+    let survivability = config.survivability.as_ref().map_or(0., |survivability| {
+        effective_health(stats, config.max_level, survivability) * survivability.weight
+    });
+
+    damage + survivability
+}
+
+/// The expected damage of a move against the configured target, counting what it steals back
+/// as healing if the config asks for it
+pub(crate) fn move_damage(
+    stats: &Characteristic,
+    damaging_move: &DamagingMove,
+    config: &Config,
+) -> f64 {
+    let damage = damage::damage(
+        stats,
+        damaging_move,
+        &DamageOptions {
+            critical: Critical::Average,
+            target: Some(&config.target),
+        },
+    );
+
+    if config.count_steal_heals {
+        damage.total().average + damage.stolen().average
+    } else {
+        damage.total().average
+    }
+}
+
+/// The amount of raw damage it takes to kill a character with these stats
+pub(crate) fn effective_health(
+    stats: &Characteristic,
+    level: i32,
+    survivability: &SurvivabilityOptimisation,
+) -> f64 {
+    let health = (50 + level * 5 + stats[Stat::Vitality]).max(1) as f64;
+
+    let (raw_hit, critical_extra) = match &survivability.critical {
+        Some(critical) => (
+            survivability.incoming_hit + critical.chance * critical.bonus_damage,
+            critical.chance
+                * (critical.bonus_damage - stats[Stat::ResistanceCritical] as f64).max(0.),
+        ),
+        None => (survivability.incoming_hit, 0.),
+    };
+
+    let reach_multiplier = survivability.melee_share.map_or(1., |melee_share| {
+        melee_share * resistance_multiplier(stats[Stat::ResistanceMelee])
+            + (1. - melee_share) * resistance_multiplier(stats[Stat::ResistanceRange])
+    });
+
+    let total_mix = survivability.element_mix.iter().sum::<f64>();
+    if total_mix <= 0. || raw_hit <= 0. {
+        return health;
+    }
+
+    let taken_hit = survivability
+        .element_mix
+        .iter()
+        .zip(Element::ALL)
+        .map(|(mix, element)| {
+            let taken = (survivability.incoming_hit + critical_extra
+                - stats[element.resistance_fixed()] as f64)
+                .max(0.)
+                * resistance_multiplier(stats[element.resistance_percent()]);
+            taken * mix / total_mix
+        })
+        .sum::<f64>()
+        * reach_multiplier;
+
+    // fully resisting every hit would make the set immortal, cap how much that's worth
+    health * raw_hit / taken_hit.max(raw_hit / 100.)
+}
+
+/// The split of at most `budget` points between the characteristics that gains the most, where
+/// `gains[characteristic][points]` is the gain from putting `points` into that characteristic
+fn best_point_split(gains: &[Vec<f64>; 6], budget: usize) -> [i32; 6] {
+    // most[c][b] is the most the characteristics from c onwards can gain with b points, and
+    // spend[c][b] is how many of those points go into c to get it
+    let mut most = vec![vec![0.; budget + 1]; gains.len() + 1];
+    let mut spend = vec![vec![0; budget + 1]; gains.len()];
+
+    for characteristic in (0..gains.len()).rev() {
+        for available in 0..=budget {
+            let mut best = (0, f64::NEG_INFINITY);
+            for points in 0..=available {
+                let gain =
+                    gains[characteristic][points] + most[characteristic + 1][available - points];
+                // fewer points wins a tie, leaving them for the characteristics after
+                if gain > best.1 {
+                    best = (points, gain);
+                }
+            }
+
+            spend[characteristic][available] = best.0;
+            most[characteristic][available] = best.1;
+        }
+    }
+
+    let mut available = budget;
+    std::array::from_fn(|characteristic| {
+        let points = spend[characteristic][available];
+        available -= points;
+        points as i32
+    })
 }
 
 fn level_initial_ap(level: i32) -> i32 {
@@ -361,15 +576,38 @@ pub struct Optimiser<'a> {
     items: &'a Items,
     initial_state: State,
     item_list: AllowedItemCache,
+    /// The allowed items of every set with at least two of them, for set moves
+    set_items: Vec<Vec<(ItemType, ItemIndex)>>,
+    dofus_slots: Vec<usize>,
+    move_weights: MoveWeights,
     temperature_initial: f64,
-    temperature_time_constant: f64,
-    temperature_quench: f64,
+    estimate_temperature: bool,
+    cooling_schedule: CoolingSchedule,
+    seed: u64,
 }
 
-struct AllowedItemCache {
+/// The items that can go in each item type's slots, within the level and not banned
+pub(crate) struct AllowedItemCache {
     items: [Vec<ItemIndex>; 10],
 }
 
+impl AllowedItemCache {
+    pub(crate) fn new(config: &Config, items: &Items) -> Self {
+        let mut item_list: [Vec<ItemIndex>; 10] = Default::default();
+
+        for (idx, item_list) in item_list.iter_mut().enumerate() {
+            *item_list = items[ItemType::from(idx)]
+                .iter()
+                .filter(|&x| items[*x].level <= config.max_level)
+                .filter(|&x| !config.ban_list.contains(x))
+                .copied()
+                .collect();
+        }
+
+        AllowedItemCache { items: item_list }
+    }
+}
+
 impl Index<ItemType> for AllowedItemCache {
     type Output = [ItemIndex];
 
@@ -387,49 +625,620 @@ impl<'a> Optimiser<'a> {
         let initial_state: State =
             State::new_from_initial_equipment(config.initial_set.map(NicheItemIndex::get), items)?;
 
-        let mut item_list: [Vec<ItemIndex>; 10] = Default::default();
+        let item_list = AllowedItemCache::new(config, items);
 
-        for (idx, item_list) in item_list.iter_mut().enumerate() {
-            *item_list = items[ItemType::from(idx)]
+        let changeable_type = |item_type: ItemType| {
+            config
+                .changable
                 .iter()
-                .filter(|&x| items[*x].level <= config.max_level)
-                .filter(|&x| !config.ban_list.contains(x))
-                .copied()
-                .collect();
+                .any(|&slot| slot_index_to_item_type(slot) == item_type)
+        };
+
+        let mut set_items = BTreeMap::<SetIndex, Vec<(ItemType, ItemIndex)>>::new();
+        for item_type in (0..10).map(ItemType::from) {
+            if !changeable_type(item_type) {
+                continue;
+            }
+
+            for &item in &item_list[item_type] {
+                if let Some(set) = items[item].set_id {
+                    set_items.entry(set).or_default().push((item_type, item));
+                }
+            }
         }
+        let set_items = set_items
+            .into_values()
+            .filter(|set_items| set_items.len() >= 2)
+            .collect();
+
+        let dofus_slots = config
+            .changable
+            .iter()
+            .copied()
+            .filter(|&slot| slot_index_to_item_type(slot) == ItemType::Dofus)
+            .collect();
 
-        let temperature_initial = initial_temperature;
-        let temperature_quench = 5.;
-        let temperature_time_constant =
-            (0.01f64 / temperature_initial).ln() / 0.95_f64.powf(temperature_quench);
         Ok(Optimiser {
             config,
             initial_state,
-            item_list: AllowedItemCache { items: item_list },
-            temperature_initial,
-            temperature_time_constant,
-            temperature_quench,
+            item_list,
+            set_items,
+            dofus_slots,
+            move_weights: MoveWeights::default(),
+            temperature_initial: initial_temperature,
+            estimate_temperature: false,
+            cooling_schedule: CoolingSchedule::default(),
             items,
+            seed: rand::random(),
+        })
+    }
+
+    /// Makes the optimisation deterministic, the same seed with the same config and items will
+    /// always find the same set. A random seed is used otherwise.
+    pub fn with_seed(self, seed: u64) -> Self {
+        Optimiser { seed, ..self }
+    }
+
+    /// The seed this optimiser will use, which can be given to `with_seed` to repeat the run
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn with_cooling_schedule(self, cooling_schedule: CoolingSchedule) -> Self {
+        Optimiser {
+            cooling_schedule,
+            ..self
+        }
+    }
+
+    /// Ignores the given initial temperature and instead picks one from a random walk away from
+    /// the initial state, so that most moves that make the set worse are accepted at the start
+    pub fn with_estimated_initial_temperature(self) -> Self {
+        Optimiser {
+            estimate_temperature: true,
+            ..self
+        }
+    }
+
+    fn estimated_initial_temperature<R: Rng>(&self, rng: &mut R) -> Result<f64, OptimiseError> {
+        let mut state = self.initial_state.clone();
+        let mut energy = self.energy(&state);
+        let mut increases = Vec::new();
+
+        for _ in 0..TEMPERATURE_SAMPLES {
+            let (neighbour, neighbour_energy) = self.neighbour(&state, rng)?;
+            if neighbour_energy > energy {
+                increases.push(neighbour_energy - energy);
+            }
+
+            state = neighbour;
+            energy = neighbour_energy;
+        }
+
+        // nothing made the set worse, so the temperature barely matters
+        if increases.is_empty() {
+            return Ok(1.);
+        }
+
+        let average_increase = increases.iter().sum::<f64>() / increases.len() as f64;
+        Ok(-average_increase / INITIAL_ACCEPTANCE.ln())
+    }
+
+    /// Works out the initial temperature now if it's going to be estimated
+    fn resolve_temperature<R: Rng>(self, rng: &mut R) -> Result<Self, OptimiseError> {
+        if !self.estimate_temperature {
+            return Ok(self);
+        }
+
+        Ok(Optimiser {
+            temperature_initial: self.estimated_initial_temperature(rng)?,
+            estimate_temperature: false,
+            ..self
         })
     }
 
-    pub fn optimise(self, iterations: i64) -> Result<State, OptimiseError> {
-        if !self
+    pub fn optimise(self, iterations: i64) -> Result<Annealed<State>, OptimiseError> {
+        self.optimise_observed(iterations, &mut ())
+    }
+
+    /// Optimises while reporting progress to the observer, which can also stop the run early
+    pub fn optimise_observed(
+        self,
+        iterations: i64,
+        observer: &mut impl Observer<State>,
+    ) -> Result<Annealed<State>, OptimiseError> {
+        let energy = self.energy(&self.initial_state);
+        if self.nothing_to_change() {
+            return Ok(Annealed::new(self.initial_state, energy));
+        }
+
+        let mut rng = StdRng::seed_from_u64(self.seed);
+        let optimiser = self.resolve_temperature(&mut rng)?;
+        let annealed = Anneal::optimise(
+            &optimiser,
+            (optimiser.initial_state.clone(), energy),
+            iterations,
+            &mut rng,
+            observer,
+        )?;
+
+        Ok(optimiser.polish_best(annealed))
+    }
+
+    /// Runs several annealing chains on their own threads and returns the best valid state any
+    /// of them found, which is much less sensitive to an unlucky run than a single chain
+    pub fn optimise_parallel(
+        self,
+        iterations: i64,
+        options: &ParallelOptions,
+    ) -> Result<Annealed<State>, OptimiseError> {
+        let (optimiser, chains) = self.parallel_chains(iterations, options)?;
+        Ok(optimiser.polish_best(optimiser.best_chain(chains)))
+    }
+
+    /// Where each chain of a parallel run finished, along with the optimiser they ran with
+    fn parallel_chains(
+        self,
+        iterations: i64,
+        options: &ParallelOptions,
+    ) -> Result<(Optimiser<'a>, Vec<Annealed<State>>), OptimiseError> {
+        let energy = self.energy(&self.initial_state);
+        let start = Annealed::new(self.initial_state.clone(), energy);
+        if self.nothing_to_change() {
+            return Ok((self, vec![start]));
+        }
+
+        let mut chains = vec![start; options.chains.max(1)];
+        let mut seeds = StdRng::seed_from_u64(self.seed);
+        let optimiser = self.resolve_temperature(&mut seeds)?;
+        let mut rngs = chains
+            .iter()
+            .map(|_| StdRng::seed_from_u64(seeds.gen()))
+            .collect::<Vec<_>>();
+
+        let rounds = options.exchanges as i64 + 1;
+        for round in 0..rounds {
+            let round_iterations = iterations * round / rounds..iterations * (round + 1) / rounds;
+            let optimiser = &optimiser;
+
+            chains = std::thread::scope(|scope| {
+                let handles = chains
+                    .into_iter()
+                    .zip(rngs.iter_mut())
+                    .map(|(chain, rng)| {
+                        let round_iterations = round_iterations.clone();
+                        scope.spawn(move || {
+                            optimiser.anneal(chain, round_iterations, iterations, rng, &mut ())
+                        })
+                    })
+                    .collect::<Vec<_>>();
+
+                handles
+                    .into_iter()
+                    .map(|handle| handle.join().expect("annealing chain panicked"))
+                    .collect::<Result<Vec<_>, _>>()
+            })?;
+
+            // every chain carries on from the best state so far for the next round
+            if round + 1 < rounds {
+                let best = optimiser.best_chain(chains.iter().cloned());
+                chains.fill(Annealed::new(best.best, best.best_energy));
+            }
+        }
+
+        Ok((optimiser, chains))
+    }
+
+    /// Finds several good valid sets that each differ from the others by at least
+    /// `min_distance` items, best first
+    pub fn optimise_diverse(
+        self,
+        iterations: i64,
+        options: &DiverseOptions,
+    ) -> Result<Vec<(State, f64)>, OptimiseError> {
+        let mut rng = StdRng::seed_from_u64(self.seed);
+        let optimiser = self.resolve_temperature(&mut rng)?;
+        let energy = optimiser.energy(&optimiser.initial_state);
+
+        let mut pool = DiversePool {
+            optimiser: &optimiser,
+            options,
+            states: Vec::new(),
+        };
+        pool.offer(&optimiser.initial_state, energy);
+
+        if !optimiser.nothing_to_change() {
+            let annealed = Anneal::optimise(
+                &optimiser,
+                (optimiser.initial_state.clone(), energy),
+                iterations,
+                &mut rng,
+                &mut pool,
+            )?;
+
+            if !annealed.stopped {
+                let (best, best_energy) = optimiser.polish(annealed.best);
+                pool.offer(&best, best_energy);
+            }
+        }
+
+        Ok(pool.states)
+    }
+
+    /// Makes the best single change to the state over and over until no change lowers its energy.
+    /// Every allowed item is tried in every changeable slot, along with moving characteristic
+    /// points between characteristics and reallocating all of them at once. A valid state is
+    /// never made invalid.
+    pub fn polish(&self, state: State) -> (State, f64) {
+        let mut energy = self.energy(&state);
+        let mut valid = self.is_valid(&state);
+        let mut state = state;
+
+        loop {
+            let mut improvement = None;
+            let mut improved_energy = energy;
+
+            for candidate in self.single_changes(&state) {
+                let candidate_energy = self.energy(&candidate);
+                if candidate_energy < improved_energy && (!valid || self.is_valid(&candidate)) {
+                    improvement = Some(candidate);
+                    improved_energy = candidate_energy;
+                }
+            }
+
+            if improvement.is_none() && self.config.consider_characteristics {
+                let allocated = self.allocate_points(&state);
+                let allocated_energy = self.energy(&allocated);
+                if allocated_energy < energy && (!valid || self.is_valid(&allocated)) {
+                    improvement = Some(allocated);
+                    improved_energy = allocated_energy;
+                }
+            }
+
+            let Some(improvement) = improvement else {
+                return (state, energy);
+            };
+
+            state = improvement;
+            energy = improved_energy;
+            valid = valid || self.is_valid(&state);
+        }
+    }
+
+    /// Spends the characteristic points in the way that lowers the energy the most for the items
+    /// of the state. This is exact when each characteristic's effect on the energy doesn't depend
+    /// on the others, like it is for weighted stats, targets, damage and survivability.
+    ///
+    /// When they do depend on each other, like with multi element or item conditions on several
+    /// characteristics, each characteristic's gains are worked out with the others' points kept
+    /// as they are, and the split is refined that way until it stops lowering the energy. That
+    /// finds a split no worse than the state's own, but not always the best one.
+    pub fn allocate_points(&self, state: &State) -> State {
+        let budget = self.config.characteristics_point().max(0);
+        // overspending is only a penalty for the split as a whole, so it's left out of the gains
+        let energy_within_budget =
+            |state: &State| self.energy(state) - state.overspending_energy(self.config);
+
+        let mut best = state.clone();
+        let mut best_energy = self.energy(state);
+        let mut split = state.clone();
+
+        for _ in 0..ALLOCATION_SWEEPS {
+            let gains = std::array::from_fn(|characteristic| {
+                let mut changed = split.clone();
+                changed.characteristic_points[characteristic] = 0;
+                let base_energy = energy_within_budget(&changed);
+
+                (0..=budget)
+                    .map(|points| {
+                        changed.characteristic_points[characteristic] = points;
+                        base_energy - energy_within_budget(&changed)
+                    })
+                    .collect()
+            });
+
+            split.characteristic_points = best_point_split(&gains, budget as usize);
+            let energy = self.energy(&split);
+            if energy >= best_energy {
+                break;
+            }
+
+            best = split.clone();
+            best_energy = energy;
+        }
+
+        best
+    }
+
+    fn polish_best(&self, annealed: Annealed<State>) -> Annealed<State> {
+        // a run that was stopped should come back as soon as possible
+        if annealed.stopped {
+            return annealed;
+        }
+
+        let (best, best_energy) = self.polish(annealed.best);
+
+        Annealed {
+            best,
+            best_energy,
+            ..annealed
+        }
+    }
+
+    /// Every state that is one item swap or one move of characteristic points away from this one,
+    /// where points move between the characteristics and the ones that haven't been spent
+    fn single_changes<'b>(&'b self, state: &'b State) -> impl Iterator<Item = State> + 'b {
+        let item_changes = self.config.changable.iter().flat_map(move |&slot| {
+            let allowed = &self.item_list[slot_index_to_item_type(slot)];
+
+            std::iter::once(None)
+                .chain(allowed.iter().copied().map(Some))
+                .filter(move |&item| state.set[slot].get() != item)
+                .map(move |item| {
+                    let mut changed = state.clone();
+                    changed.set_item(slot, item, self.items);
+                    changed
+                })
+        });
+
+        let point_changes = (0..POINT_POOLS)
+            .flat_map(|from| (0..POINT_POOLS).map(move |to| (from, to)))
+            .filter(|&(from, to)| self.config.consider_characteristics && from != to)
+            .flat_map(move |(from, to)| {
+                let available = state.points_in_pool(self.config, from);
+
+                POINT_STEPS.into_iter().filter_map(move |step| {
+                    let mut changed = state.clone();
+                    changed
+                        .move_points(self.config, from, to, step.min(available))
+                        .then_some(changed)
+                })
+            });
+
+        item_changes.chain(point_changes)
+    }
+
+    /// Moves some characteristic points between two characteristics, or between a characteristic
+    /// and the unspent points, so no more than the budget is ever spent
+    fn point_move<R: Rng>(&self, state: &mut State, rng: &mut R) {
+        let from = rng.gen_range(0..POINT_POOLS);
+        let to = (0..POINT_POOLS)
+            .filter(|&to| to != from)
+            .choose(rng)
+            .expect("there's more than one pool");
+
+        let available = state.points_in_pool(self.config, from);
+        let largest_step = POINT_STEPS.choose(rng).copied().unwrap_or(1).min(available);
+        if largest_step > 0 {
+            state.move_points(self.config, from, to, rng.gen_range(1..=largest_step));
+        }
+    }
+
+    pub fn with_move_weights(self, move_weights: MoveWeights) -> Self {
+        Optimiser {
+            move_weights,
+            ..self
+        }
+    }
+
+    /// Picks the kind of neighbour move to make, leaving out any that can't do anything
+    fn choose_move<R: Rng>(&self, rng: &mut R) -> Move {
+        let weights = &self.move_weights;
+        // swapping rings or dofus only changes which slot they're in, which only matters to the
+        // energy when changed items are weighted
+        let slots_matter = self.config.changed_item_weight != 0.;
+        let rings_changeable = slots_matter
+            && RING_SLOTS
+                .iter()
+                .all(|slot| self.config.changable.contains(slot));
+
+        let moves = [
+            (Move::Item, weights.item),
+            (
+                Move::Characteristic,
+                if self.config.consider_characteristics {
+                    weights.characteristic
+                } else {
+                    0.
+                },
+            ),
+            (
+                Move::Set,
+                if self.set_items.is_empty() {
+                    0.
+                } else {
+                    weights.set
+                },
+            ),
+            (
+                Move::RingSwap,
+                if rings_changeable {
+                    weights.ring_swap
+                } else {
+                    0.
+                },
+            ),
+            (
+                Move::DofusSwap,
+                if slots_matter && self.dofus_slots.len() >= 2 {
+                    weights.dofus_swap
+                } else {
+                    0.
+                },
+            ),
+        ];
+
+        moves
+            .choose_weighted(rng, |(_, weight)| weight.max(0.))
+            .map_or(Move::Item, |(kind, _)| *kind)
+    }
+
+    /// Puts several allowed items from one set into the slots they fit in
+    fn set_move<R: Rng>(&self, state: &mut State, rng: &mut R) {
+        let set_items = self
+            .set_items
+            .choose(rng)
+            .expect("set moves are only made when there are sets");
+        let count = rng.gen_range(2..=set_items.len());
+
+        let mut filled = Vec::with_capacity(count);
+        for &(item_type, item) in set_items.choose_multiple(rng, count) {
+            let slot = self
+                .config
+                .changable
+                .iter()
+                .copied()
+                .filter(|&slot| slot_index_to_item_type(slot) == item_type)
+                .filter(|slot| !filled.contains(slot))
+                .choose(rng);
+
+            if let Some(slot) = slot {
+                state.set_item(slot, Some(item), self.items);
+                filled.push(slot);
+            }
+        }
+    }
+
+    fn nothing_to_change(&self) -> bool {
+        !self
             .config
             .changable
             .iter()
             .any(|&x| !self.item_list[slot_index_to_item_type(x)].is_empty())
+    }
+
+    fn energy(&self, state: &State) -> f64 {
+        let sets = state.sets(self.items);
+        state.energy(self.config, self.items, &sets)
+    }
+
+    /// The chain with the lowest energy valid best state, or the lowest energy one if none are valid
+    fn best_chain(&self, chains: impl IntoIterator<Item = Annealed<State>>) -> Annealed<State> {
+        chains
+            .into_iter()
+            .map(|chain| (!self.is_valid(&chain.best), chain))
+            .min_by(|(a_invalid, a), (b_invalid, b)| {
+                a_invalid
+                    .cmp(b_invalid)
+                    .then(a.best_energy.total_cmp(&b.best_energy))
+            })
+            .map(|(_, chain)| chain)
+            .expect("there should be at least one chain")
+    }
+}
+
+/// How likely each kind of neighbour move is, relative to the others
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct MoveWeights {
+    /// Change the item in one slot
+    pub item: f64,
+    /// Move points between characteristics, only used if characteristics are considered
+    pub characteristic: f64,
+    /// Put several items from the same set on at once
+    pub set: f64,
+    /// Swap the two rings, only used if changed items are weighted
+    pub ring_swap: f64,
+    /// Swap two dofus, only used if changed items are weighted
+    pub dofus_swap: f64,
+}
+
+impl Default for MoveWeights {
+    fn default() -> Self {
+        MoveWeights {
+            item: 51.,
+            characteristic: 6.,
+            set: 3.,
+            ring_swap: 1.,
+            dofus_swap: 1.,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Move {
+    Item,
+    Characteristic,
+    Set,
+    RingSwap,
+    DofusSwap,
+}
+
+#[derive(Clone, Debug)]
+pub struct DiverseOptions {
+    /// The most sets to find
+    pub count: usize,
+    /// The fewest slots any two of the sets can differ by, at least 1
+    pub min_distance: usize,
+}
+
+/// Keeps the best valid states seen that are all far enough apart, sorted best first
+struct DiversePool<'b, 'a> {
+    optimiser: &'b Optimiser<'a>,
+    options: &'b DiverseOptions,
+    states: Vec<(State, f64)>,
+}
+
+impl DiversePool<'_, '_> {
+    fn offer(&mut self, state: &State, energy: f64) {
+        let count = self.options.count.max(1);
+        let min_distance = self.options.min_distance.max(1);
+
+        if self.states.len() >= count
+            && self
+                .states
+                .last()
+                .is_some_and(|(_, worst)| energy >= *worst)
+        {
+            return;
+        }
+
+        // a state can only replace the states close to it if it's better than all of them
+        let close = |other: &State| state.distance(other) < min_distance;
+        if self
+            .states
+            .iter()
+            .any(|(other, other_energy)| close(other) && *other_energy <= energy)
         {
-            return Ok(self.initial_state);
+            return;
         }
 
-        let sets = self.initial_state.sets(self.items);
-        let energy = self.initial_state.energy(self.config, self.items, &sets);
+        if !self.optimiser.is_valid(state) {
+            return;
+        }
 
-        anneal::Anneal::optimise(&self, (self.initial_state.clone(), energy), iterations)
+        self.states.retain(|(other, _)| !close(other));
+        let position = self
+            .states
+            .partition_point(|(_, other_energy)| *other_energy <= energy);
+        self.states.insert(position, (state.clone(), energy));
+        self.states.truncate(count);
     }
 }
 
+impl Observer<State> for DiversePool<'_, '_> {
+    fn interval(&self) -> i64 {
+        i64::MAX
+    }
+
+    fn progress(&mut self, _progress: &anneal::Progress<State>) -> ControlFlow<()> {
+        ControlFlow::Continue(())
+    }
+
+    fn accepted(&mut self, state: &State, energy: f64) {
+        self.offer(state, energy);
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ParallelOptions {
+    /// The number of independent chains, each run on its own thread
+    pub chains: usize,
+    /// How many times the chains stop to all carry on from the best state found so far, 0 keeps
+    /// them independent for the whole run
+    pub exchanges: usize,
+}
+
 #[derive(Debug, thiserror::Error, Serialize)]
 pub enum OptimiseError {
     #[error("could not find neighbour after {0} attempts")]
@@ -441,49 +1250,65 @@ pub enum OptimiseError {
     },
     #[error("the given state is not valid even with leniency")]
     InvalidState,
+    #[error("could not search every set in {0} steps")]
+    SearchSpaceTooLarge(u64),
+    #[error("objective {0} refers to a stat or damaging move that doesn't exist")]
+    InvalidObjective(usize),
+    #[error("there is no class called {0}")]
+    UnknownClass(String),
+    #[error("no class was given to look up {0} in")]
+    MissingClass(String),
+    #[error("the class has no spell called {0}")]
+    UnknownSpell(String),
+    #[error("{spell} doesn't deal damage at level {level}")]
+    NoSpellDamage { spell: String, level: i32 },
+    #[error("the costs of characteristic {0} must start from 0, rise and all be above 0")]
+    InvalidCostTable(usize),
+    #[error("the exact solver doesn't support {0}")]
+    ExactSolverUnsupported(&'static str),
 }
 
-impl anneal::Anneal<State> for Optimiser<'_> {
+impl Anneal<State> for Optimiser<'_> {
     type Error = OptimiseError;
 
-    fn random(&self) -> f64 {
-        rand::thread_rng().gen_range(0.0..1.0)
+    fn is_valid(&self, state: &State) -> bool {
+        let sets = state.sets(self.items);
+        let stats = state.stats(self.config, &sets);
+        state.is_valid(self.config, &stats, self.items, &sets)
     }
 
-    fn neighbour(&self, state: &State) -> Result<(State, f64), OptimiseError> {
-        let mut rng = rand::thread_rng();
-
+    fn neighbour<R: Rng>(&self, state: &State, rng: &mut R) -> Result<(State, f64), OptimiseError> {
         let mut new_state = state.clone();
 
-        if !self.config.consider_characteristics || rng.gen_ratio(51, 51 + 6) {
-            let (item_slot, item) = loop {
-                let item_slot = *self.config.changable.choose(&mut rng).unwrap();
-                let item_type = &self.item_list[slot_index_to_item_type(item_slot)];
-                if item_type.is_empty() {
-                    continue;
-                }
-                let idx = rng.gen_range(0..item_type.len() + 1);
-                if idx != item_type.len() {
-                    let item_index = item_type[idx];
-                    break (item_slot, Some(item_index));
-                } else {
-                    break (item_slot, None);
-                }
-            };
+        match self.choose_move(rng) {
+            Move::Item => {
+                let (item_slot, item) = loop {
+                    let item_slot = *self.config.changable.choose(rng).unwrap();
+                    let item_type = &self.item_list[slot_index_to_item_type(item_slot)];
+                    if item_type.is_empty() {
+                        continue;
+                    }
+                    let idx = rng.gen_range(0..item_type.len() + 1);
+                    if idx != item_type.len() {
+                        let item_index = item_type[idx];
+                        break (item_slot, Some(item_index));
+                    } else {
+                        break (item_slot, None);
+                    }
+                };
 
-            if let Some(old_item) = new_state.set[item_slot].get() {
-                new_state.remove_item(&self.items[old_item]);
+                new_state.set_item(item_slot, item, self.items);
             }
-            if let Some(item) = item {
-                new_state.add_item(&self.items[item]);
+            Move::Characteristic => self.point_move(&mut new_state, rng),
+            Move::Set => self.set_move(&mut new_state, rng),
+            // swapping slots doesn't change the stats, only how far the set is from the initial set
+            Move::RingSwap => new_state.set.swap(RING_SLOTS[0], RING_SLOTS[1]),
+            Move::DofusSwap => {
+                let mut slots = self.dofus_slots.choose_multiple(rng, 2);
+                if let (Some(a), Some(b)) = (slots.next(), slots.next()) {
+                    new_state.set.swap(*a, *b);
+                }
             }
-
-            new_state.set[item_slot] = NicheItemIndex::new(item);
-        } else {
-            *new_state
-                .characteristic_points
-                .choose_mut(&mut rng)
-                .unwrap() = rng.gen_range(0..=self.config.characteristics_point());
         }
         let sets = new_state.sets(self.items);
 
@@ -491,10 +1316,13 @@ impl anneal::Anneal<State> for Optimiser<'_> {
         Ok((new_state, energy))
     }
 
-    fn temperature(&self, iteration: f64) -> f64 {
-        self.temperature_initial
-            * std::f64::consts::E
-                .powf(self.temperature_time_constant * iteration.powf(self.temperature_quench))
+    fn temperature(&self, fraction: f64, heat: Heat) -> f64 {
+        self.cooling_schedule
+            .reheated_temperature(self.temperature_initial, fraction, heat)
+    }
+
+    fn reheat_below(&self) -> Option<(i64, f64)> {
+        self.cooling_schedule.reheat_below()
     }
 }
 
@@ -502,14 +1330,651 @@ impl anneal::Anneal<State> for Optimiser<'_> {
 mod tests {
 
     use super::*;
+    use crate::{
+        anneal::Progress,
+        characteristic_points::{CharacteristicRules, CostTable, CostTier},
+        config::{
+            test_config, BaseDamage, DamagingMove, DamagingMovesOptimisation, MoveKind, MoveLine,
+            Reach, TargetProfile,
+        },
+    };
+    use dofus_items::ITEMS;
 
     #[test]
     fn check_characteristic_calculation() {
-        assert_eq!(calculate_points_for_stat(50), 50);
-        assert_eq!(calculate_points_for_stat(100), 100);
+        let strength = &CharacteristicRules::default().costs[4];
+
+        assert_eq!(strength.gain(50), 50);
+        assert_eq!(strength.gain(100), 100);
+
+        assert_eq!(strength.gain(150), 125);
+        assert_eq!(strength.gain(688), 322);
+        assert_eq!(strength.gain(1000), 400);
+    }
+
+    #[test]
+    fn effective_health_scales_with_resistances() {
+        let survivability = SurvivabilityOptimisation {
+            weight: 1.,
+            element_mix: [1., 0., 0., 0., 0.],
+            incoming_hit: 100.,
+            melee_share: None,
+            critical: None,
+        };
+
+        let mut stats = Characteristic::new();
+        stats[Stat::Vitality] = 445;
+        assert_eq!(effective_health(&stats, 1, &survivability), 500.);
+
+        stats[Stat::ResistanceNeutralPercent] = 50;
+        assert_eq!(effective_health(&stats, 1, &survivability), 1000.);
+
+        stats[Stat::ResistanceNeutralFixed] = 50;
+        assert_eq!(effective_health(&stats, 1, &survivability), 2000.);
+
+        // resistances in elements that aren't hit don't matter
+        stats[Stat::ResistanceFirePercent] = 50;
+        assert_eq!(effective_health(&stats, 1, &survivability), 2000.);
+    }
+
+    #[test]
+    fn diverse_sets_are_far_enough_apart() {
+        let mut weights = [0.0; 51];
+        weights[Stat::Vitality as usize] = 1.0;
+        weights[Stat::AP as usize] = 400.0;
+
+        let config = Config {
+            max_level: 100,
+            weights,
+            changable: (0..16).collect(),
+            ..test_config()
+        };
+
+        let sets = Optimiser::new(&config, 1000., &ITEMS)
+            .unwrap()
+            .with_seed(1)
+            .optimise_diverse(
+                20_000,
+                &DiverseOptions {
+                    count: 4,
+                    min_distance: 3,
+                },
+            )
+            .unwrap();
+
+        assert!(!sets.is_empty() && sets.len() <= 4);
+        for (i, (a, a_energy)) in sets.iter().enumerate() {
+            for (b, b_energy) in &sets[i + 1..] {
+                assert!(a.distance(b) >= 3);
+                assert!(a_energy <= b_energy);
+            }
+        }
+    }
+
+    #[test]
+    fn the_same_seed_finds_the_same_set() {
+        let mut weights = [0.0; 51];
+        weights[Stat::Vitality as usize] = 1.0;
+        weights[Stat::AP as usize] = 400.0;
+        let config = Config {
+            max_level: 100,
+            weights,
+            changable: (0..16).collect(),
+            consider_characteristics: true,
+            ..test_config()
+        };
+        let run = |seed| {
+            Optimiser::new(&config, 1000., &ITEMS)
+                .unwrap()
+                .with_seed(seed)
+                .with_estimated_initial_temperature()
+                .optimise(5_000)
+                .unwrap()
+        };
+
+        let first = run(7);
+        let again = run(7);
+        assert_eq!(first.best_energy, again.best_energy);
+        assert_eq!(first.last_energy, again.last_energy);
+        assert!((0..SLOTS).all(|slot| first.best.item(slot) == again.best.item(slot)));
+        assert_eq!(first.best.points(), again.best.points());
+    }
+
+    #[test]
+    fn parallel_runs_repeat_and_beat_their_chains() {
+        let mut weights = [0.0; 51];
+        weights[Stat::Vitality as usize] = 1.0;
+        weights[Stat::AP as usize] = 400.0;
+        let config = Config {
+            max_level: 100,
+            weights,
+            changable: (0..16).collect(),
+            ..test_config()
+        };
+        let options = ParallelOptions {
+            chains: 3,
+            exchanges: 1,
+        };
+        let optimiser = || Optimiser::new(&config, 1000., &ITEMS).unwrap().with_seed(7);
+
+        let run = optimiser().optimise_parallel(5_000, &options).unwrap();
+        let again = optimiser().optimise_parallel(5_000, &options).unwrap();
+        assert_eq!(run.best_energy, again.best_energy);
+        assert!((0..SLOTS).all(|slot| run.best.item(slot) == again.best.item(slot)));
+
+        // the same seed runs the same chains again
+        let (optimiser, chains) = optimiser().parallel_chains(5_000, &options).unwrap();
+        let best_chain = chains
+            .iter()
+            .filter(|chain| optimiser.is_valid(&chain.best))
+            .map(|chain| chain.best_energy)
+            .fold(f64::INFINITY, f64::min);
+        assert!(optimiser.is_valid(&run.best));
+        assert!(run.best_energy <= best_chain);
+    }
+
+    #[test]
+    fn stopped_runs_arent_polished() {
+        struct Stop;
+
+        impl Observer<State> for Stop {
+            fn interval(&self) -> i64 {
+                1
+            }
+
+            fn progress(&mut self, _progress: &Progress<State>) -> ControlFlow<()> {
+                ControlFlow::Break(())
+            }
+        }
+
+        let mut weights = [0.0; 51];
+        weights[Stat::Vitality as usize] = 1.0;
+        let config = Config {
+            weights,
+            changable: (0..16).collect(),
+            ..test_config()
+        };
+
+        let annealed = Optimiser::new(&config, 1000., &ITEMS)
+            .unwrap()
+            .optimise_observed(1000, &mut Stop)
+            .unwrap();
+        assert!(annealed.stopped);
+        assert_eq!(annealed.best_energy, 0.);
+    }
+
+    #[test]
+    fn initial_temperature_accepts_most_moves_that_make_the_set_worse() {
+        let config = |weight: f64| {
+            let mut weights = [0.0; 51];
+            weights[Stat::Vitality as usize] = weight;
+            weights[Stat::Wisdom as usize] = weight * 3.;
+            Config {
+                max_level: 100,
+                weights,
+                changable: (0..16).collect(),
+                ..test_config()
+            }
+        };
+        let estimate = |config: &Config| {
+            Optimiser::new(config, 1000., &ITEMS)
+                .unwrap()
+                .estimated_initial_temperature(&mut StdRng::seed_from_u64(0))
+                .unwrap()
+        };
+
+        let config = config(1.);
+        let optimiser = Optimiser::new(&config, 1000., &ITEMS).unwrap();
+        let temperature = estimate(&config);
+
+        // walking the same moves again, the average one that made the set worse is accepted
+        // with the initial acceptance
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut state = optimiser.initial_state.clone();
+        let mut energy = optimiser.energy(&state);
+        let mut increases = Vec::new();
+        for _ in 0..TEMPERATURE_SAMPLES {
+            let (neighbour, neighbour_energy) = optimiser.neighbour(&state, &mut rng).unwrap();
+            if neighbour_energy > energy {
+                increases.push(neighbour_energy - energy);
+            }
+            state = neighbour;
+            energy = neighbour_energy;
+        }
+        let average = increases.iter().sum::<f64>() / increases.len() as f64;
+        let acceptance = Optimiser::accept_probability(0., average, temperature);
+        assert!((acceptance - INITIAL_ACCEPTANCE).abs() < 1e-9);
+
+        // weighting the stats more makes the moves change the energy more
+        assert!(
+            estimate(&Config {
+                weights: config.weights.map(|weight| weight * 10.),
+                ..config.clone()
+            }) > temperature
+        );
+    }
+
+    #[test]
+    fn swapped_rings_and_dofus_are_the_same_set() {
+        let ring = |i: usize| Some(ITEMS[ItemType::Ring][i]);
+        let dofus = |i: usize| Some(ITEMS[ItemType::Dofus][i]);
+
+        let mut a = State::new_from_initial_equipment([None; SLOTS], &ITEMS).unwrap();
+        a.set_item(3, ring(0), &ITEMS);
+        a.set_item(4, ring(1), &ITEMS);
+        a.set_item(9, dofus(0), &ITEMS);
+        a.set_item(12, dofus(1), &ITEMS);
+
+        let mut b = a.clone();
+        b.set_item(3, ring(1), &ITEMS);
+        b.set_item(4, ring(0), &ITEMS);
+        b.set_item(9, None, &ITEMS);
+        b.set_item(12, None, &ITEMS);
+        b.set_item(10, dofus(1), &ITEMS);
+        b.set_item(14, dofus(0), &ITEMS);
+        assert_eq!(a.distance(&b), 0);
+
+        b.set_item(4, ring(2), &ITEMS);
+        assert_eq!(a.distance(&b), 1);
+        assert_eq!(b.distance(&a), 1);
+    }
+
+    #[test]
+    fn set_moves_fill_changeable_slots_from_one_set() {
+        let config = Config {
+            max_level: 100,
+            changable: vec![0, 1, 2, 3, 5, 6, 7],
+            ..test_config()
+        };
+        let optimiser = Optimiser::new(&config, 1000., &ITEMS).unwrap();
+        let allowed = AllowedItemCache::new(&config, &ITEMS);
+        let mut rng = StdRng::seed_from_u64(0);
+        let state = State::new_from_initial_equipment([None; SLOTS], &ITEMS).unwrap();
+
+        for _ in 0..200 {
+            let mut moved = state.clone();
+            optimiser.set_move(&mut moved, &mut rng);
+
+            let changed = (0..SLOTS)
+                .filter(|&slot| moved.item(slot) != state.item(slot))
+                .collect::<Vec<_>>();
+            assert!(!changed.is_empty());
+
+            let mut sets = changed.iter().map(|&slot| {
+                assert!(config.changable.contains(&slot));
+                let item = moved.item(slot).unwrap();
+                assert!(allowed[slot_index_to_item_type(slot)].contains(&item));
+                ITEMS[item].set_id.unwrap()
+            });
+            let set = sets.next().unwrap();
+            assert!(sets.all(|other| other == set));
+
+            let equipment: [Option<ItemIndex>; SLOTS] = std::array::from_fn(|i| moved.item(i));
+            let rebuilt = State::new_from_initial_equipment(equipment, &ITEMS).unwrap();
+            assert!(moved.item_totals().iter().eq(rebuilt.item_totals().iter()));
+        }
+    }
+
+    #[test]
+    fn swap_moves_keep_the_same_items() {
+        let ring = |i: usize| Some(ITEMS[ItemType::Ring][i]);
+        let dofus = |i: usize| Some(ITEMS[ItemType::Dofus][i]);
+
+        let mut initial = State::new_from_initial_equipment([None; SLOTS], &ITEMS).unwrap();
+        initial.set_item(3, ring(0), &ITEMS);
+        initial.set_item(4, ring(1), &ITEMS);
+        initial.set_item(9, dofus(0), &ITEMS);
+        initial.set_item(10, dofus(1), &ITEMS);
+
+        let mut config = Config {
+            changable: vec![3, 4, 9, 10, 11],
+            changed_item_weight: -1.,
+            ..test_config()
+        };
+        config.initial_set = std::array::from_fn(|i| NicheItemIndex::new(initial.item(i)));
+        let optimiser = Optimiser::new(&config, 1000., &ITEMS)
+            .unwrap()
+            .with_move_weights(MoveWeights {
+                item: 0.,
+                characteristic: 0.,
+                set: 0.,
+                ring_swap: 1.,
+                dofus_swap: 1.,
+            });
+        let mut rng = StdRng::seed_from_u64(0);
+
+        let stats = |state: &State| state.stats(&config, &state.sets(&ITEMS));
+        for _ in 0..100 {
+            let (swapped, energy) = optimiser.neighbour(&initial, &mut rng).unwrap();
+
+            assert_eq!(initial.distance(&swapped), 0);
+            assert!(stats(&initial).iter().eq(stats(&swapped).iter()));
+            for slot in (0..SLOTS).filter(|slot| !config.changable.contains(slot)) {
+                assert_eq!(swapped.item(slot), initial.item(slot));
+            }
+            // every slot that changed counts as a changed item, penalised by the weight
+            let changed = (0..SLOTS)
+                .filter(|&slot| swapped.item(slot) != initial.item(slot))
+                .count();
+            assert_eq!(energy, optimiser.energy(&initial) + changed as f64);
+        }
+    }
+
+    #[test]
+    fn moves_are_picked_by_weight() {
+        let picks = |config: &Config, move_weights: MoveWeights| {
+            let optimiser = Optimiser::new(config, 1000., &ITEMS)
+                .unwrap()
+                .with_move_weights(move_weights);
+            let mut rng = StdRng::seed_from_u64(0);
+            let moves = (0..10_000)
+                .map(|_| optimiser.choose_move(&mut rng))
+                .collect::<Vec<_>>();
+            move |kind| moves.iter().filter(|&&other| other == kind).count()
+        };
+        let weights = MoveWeights {
+            item: 1.,
+            characteristic: 1.,
+            set: 2.,
+            ring_swap: 0.,
+            dofus_swap: 4.,
+        };
+
+        let config = Config {
+            changable: (0..SLOTS).collect(),
+            changed_item_weight: 1.,
+            consider_characteristics: true,
+            ..test_config()
+        };
+        let count = picks(&config, weights.clone());
+        for (kind, share) in [
+            (Move::Item, 0.125),
+            (Move::Characteristic, 0.125),
+            (Move::Set, 0.25),
+            (Move::DofusSwap, 0.5),
+        ] {
+            assert!((count(kind) as f64 / 10_000. - share).abs() < 0.02);
+        }
+        assert_eq!(count(Move::RingSwap), 0);
+
+        // moves that can't change anything are never picked
+        let config = Config {
+            changed_item_weight: 0.,
+            consider_characteristics: false,
+            ..config
+        };
+        let count = picks(&config, weights);
+        assert_eq!(count(Move::Characteristic), 0);
+        assert_eq!(count(Move::DofusSwap), 0);
+        assert!((count(Move::Set) as f64 / 10_000. - 2. / 3.).abs() < 0.02);
+    }
+
+    #[test]
+    fn set_counts_follow_item_changes() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut state = State::new_from_initial_equipment([None; SLOTS], &ITEMS).unwrap();
+
+        for _ in 0..1000 {
+            let slot = rng.gen_range(0..SLOTS);
+            let item = ITEMS[slot_index_to_item_type(slot)]
+                .choose(&mut rng)
+                .copied()
+                .filter(|_| rng.gen_bool(0.9));
+            state.set_item(slot, item, &ITEMS);
+
+            let equipment: [Option<ItemIndex>; SLOTS] = std::array::from_fn(|i| state.item(i));
+            let rebuilt = State::new_from_initial_equipment(equipment, &ITEMS).unwrap();
+
+            let bonuses = |state: &State| {
+                let mut bonuses = state
+                    .sets(&ITEMS)
+                    .iter()
+                    .map(|set| (set.name.to_owned(), set.number_of_items))
+                    .collect::<Vec<_>>();
+                bonuses.sort();
+                bonuses
+            };
+            assert_eq!(bonuses(&state), bonuses(&rebuilt));
+            assert!(state.item_totals().iter().eq(rebuilt.item_totals().iter()));
+        }
+    }
+
+    #[test]
+    fn more_sets_than_slots_dont_panic() {
+        let mut state = State::new_from_initial_equipment([None; SLOTS], &ITEMS).unwrap();
+        let mut counted = Vec::new();
+        for (_, item) in ITEMS.iter() {
+            if let Some(set) = item.set_id.filter(|set| !counted.contains(set)) {
+                counted.push(set);
+                state.add_item(item);
+            }
+            if counted.len() > SLOTS {
+                break;
+            }
+        }
+
+        assert_eq!(state.cached_set_counts.len(), SLOTS);
+    }
+
+    #[test]
+    fn allocates_points_by_the_tiered_cost() {
+        let mut weights = [0.0; 51];
+        weights[Stat::Vitality as usize] = 1.0;
+        weights[Stat::Strength as usize] = 1.5;
+
+        let config = Config {
+            weights,
+            consider_characteristics: true,
+            ..test_config()
+        };
+
+        let optimiser = Optimiser::new(&config, 1000., &ITEMS).unwrap();
+        let state = State::new_from_initial_equipment([None; SLOTS], &ITEMS).unwrap();
+
+        // strength is only worth more than vitality for the first 100 points, after that it
+        // costs 2 points a stat
+        let allocated = optimiser.allocate_points(&state);
+        assert_eq!(allocated.points(), &[895, 0, 0, 0, 100, 0]);
+    }
+
+    #[test]
+    fn allocates_points_as_well_as_trying_every_split() {
+        let mut weights = [0.0; 51];
+        weights[Stat::Vitality as usize] = 1.0;
+        weights[Stat::Strength as usize] = 2.0;
+        let mut targets = [None; 51];
+        targets[Stat::Strength as usize] = Some(4);
+
+        let line = |element, min, max| MoveLine {
+            element,
+            normal: BaseDamage { min, max },
+            critical: BaseDamage { min, max },
+            steal: false,
+        };
+        let mut characteristic_rules = CharacteristicRules::default();
+        characteristic_rules.costs[5] = CostTable(vec![
+            CostTier {
+                from: 0,
+                cost: 1,
+                gain: 1,
+            },
+            CostTier {
+                from: 3,
+                cost: 3,
+                gain: 2,
+            },
+        ]);
+
+        // level 3 has 10 points, few enough to try every split
+        let config = Config {
+            max_level: 3,
+            weights,
+            targets,
+            damaging_moves: vec![DamagingMovesOptimisation {
+                weight: 0.5,
+                damage: DamagingMove {
+                    lines: vec![line(Element::Earth, 10., 14.), line(Element::Fire, 6., 8.)],
+                    base_crit_ratio: 0,
+                    modifyable_crit: false,
+                    kind: MoveKind::Spell,
+                    reach: Reach::Ranged,
+                    heal: false,
+                },
+            }],
+            target: TargetProfile {
+                resistance_fixed: [0, 0, 0, 0, 8],
+                ..Default::default()
+            },
+            consider_characteristics: true,
+            characteristic_rules,
+            ..test_config()
+        };
+
+        let optimiser = Optimiser::new(&config, 1000., &ITEMS).unwrap();
+        let mut state = State::new_from_initial_equipment([None; SLOTS], &ITEMS).unwrap();
+        state.characteristic_points = [2, 2, 2, 2, 2, 0];
+
+        let budget = config.characteristics_point();
+        let mut splits = vec![[0; 6]];
+        for characteristic in 0..6 {
+            splits = splits
+                .into_iter()
+                .flat_map(|split| {
+                    let spent = split.iter().sum::<i32>();
+                    (0..=budget - spent).map(move |points| {
+                        let mut split = split;
+                        split[characteristic] = points;
+                        split
+                    })
+                })
+                .collect();
+        }
+        let lowest = splits
+            .into_iter()
+            .map(|split| {
+                let mut tried = state.clone();
+                tried.characteristic_points = split;
+                optimiser.energy(&tried)
+            })
+            .fold(f64::INFINITY, f64::min);
+
+        let allocated = optimiser.allocate_points(&state);
+        assert_eq!(optimiser.energy(&allocated), lowest);
+    }
+
+    #[test]
+    fn points_can_be_taken_back_when_polishing() {
+        let config = Config {
+            consider_characteristics: true,
+            ..test_config()
+        };
+
+        let optimiser = Optimiser::new(&config, 1000., &ITEMS).unwrap();
+        let mut state = State::new_from_initial_equipment([None; SLOTS], &ITEMS).unwrap();
+        state.characteristic_points[0] = 100;
+
+        assert!(optimiser
+            .single_changes(&state)
+            .any(|changed| changed.points() == [0; 6]));
+    }
+
+    #[test]
+    fn polished_sets_cant_be_improved_by_one_change() {
+        let mut weights = [0.0; 51];
+        weights[Stat::Vitality as usize] = 1.0;
+        weights[Stat::Agility as usize] = 2.0;
+        weights[Stat::AP as usize] = 300.0;
+
+        let config = Config {
+            max_level: 60,
+            weights,
+            changable: vec![0, 1, 2, 5, 6],
+            consider_characteristics: true,
+            ..test_config()
+        };
+
+        let optimiser = Optimiser::new(&config, 1000., &ITEMS).unwrap();
+        let state = State::new_from_initial_equipment([None; SLOTS], &ITEMS).unwrap();
+        let unpolished = optimiser.energy(&state);
+        let (polished, energy) = optimiser.polish(state);
+        assert!(energy < unpolished);
+        assert!(optimiser.is_valid(&polished));
+
+        for changed in optimiser.single_changes(&polished) {
+            assert!(optimiser.energy(&changed) >= energy || !optimiser.is_valid(&changed));
+        }
+    }
+
+    #[test]
+    fn scrolls_dont_change_the_cost_of_points() {
+        let config = Config {
+            consider_characteristics: true,
+            scrolls: [0, 0, 0, 0, 100, 0],
+            ..test_config()
+        };
+
+        let mut state = State::new_from_initial_equipment([None; SLOTS], &ITEMS).unwrap();
+        state.characteristic_points[4] = 100;
+
+        let stats = state.stats(&config, &state.sets(&ITEMS));
+        assert_eq!(stats[Stat::Strength], 200);
+
+        // scrolls can't give more than 100
+        let config = Config {
+            scrolls: [0, 0, 0, 0, 300, 0],
+            ..config
+        };
+        let stats = state.stats(&config, &state.sets(&ITEMS));
+        assert_eq!(stats[Stat::Strength], 200);
+    }
+
+    #[test]
+    fn scrolls_meet_item_conditions() {
+        // the cape needs more than 90 strength to wear
+        let (cape, _) = ITEMS
+            .iter()
+            .find(|(_, item)| item.name == "Kwag'U Cape")
+            .unwrap();
+        let mut state = State::new_from_initial_equipment([None; SLOTS], &ITEMS).unwrap();
+        state.set_item(1, Some(cape), &ITEMS);
+
+        let valid = |config: &Config| {
+            let sets = state.sets(&ITEMS);
+            let stats = state.stats(config, &sets);
+            state.is_valid(config, &stats, &ITEMS, &sets)
+        };
+        assert!(!valid(&test_config()));
+        assert!(valid(&Config {
+            scrolls: [0, 0, 0, 0, 100, 0],
+            ..test_config()
+        }));
+    }
+
+    #[test]
+    fn weapon_hits_use_the_equipped_weapon() {
+        let weapon = |name: &str| {
+            ITEMS
+                .iter()
+                .find(|(_, item)| item.name == name)
+                .map(|(index, _)| index)
+        };
+        let hitting = Config {
+            weapon_hit_weight: 1.,
+            ..test_config()
+        };
+
+        let mut state = State::new_from_initial_equipment([None; SLOTS], &ITEMS).unwrap();
+        let mut hit_energy = |name: &str| {
+            state.set_item(WEAPON_SLOT, weapon(name), &ITEMS);
+            let sets = state.sets(&ITEMS);
+            state.energy(&hitting, &ITEMS, &sets) - state.energy(&test_config(), &ITEMS, &sets)
+        };
 
-        assert_eq!(calculate_points_for_stat(150), 125);
-        assert_eq!(calculate_points_for_stat(688), 322);
-        assert_eq!(calculate_points_for_stat(1000), 400);
+        let hammer = hit_energy("Crick Hammer");
+        let sword = hit_energy("Bearbaric Sword");
+        assert!(hammer < 0.);
+        assert!(sword < 0.);
+        assert_ne!(hammer, sword);
+        assert_eq!(hit_energy("Crick Hammer"), hammer);
     }
 }
@@ -0,0 +1,459 @@
+//! Finds the set with the lowest energy for certain, for when few enough items can change that
+//! the search can be finished.
+
+use std::collections::BTreeMap;
+
+use dofus_characteristics::Characteristic;
+use dofus_items::{ItemIndex, ItemType, Items, NicheItemIndex, SetIndex};
+
+use crate::{
+    config::Config,
+    dofus_set::{
+        combat_value, slot_index_to_item_type, stat_value, AllowedItemCache, OptimiseError, State,
+        WEAPON_SLOT,
+    },
+};
+
+const DEFAULT_NODE_LIMIT: u64 = 10_000_000;
+
+/// Searches every set with branch and bound, skipping every set that starts with items that
+/// can't possibly beat the best set found so far.
+///
+/// Characteristic points aren't searched, so configs that consider them are refused. The bounds
+/// assume damage and survivability never go down when a stat goes up, so the weights of damaging
+/// moves, weapon hits and survivability can't be negative.
+pub struct ExactSolver<'a> {
+    config: &'a Config,
+    items: &'a Items,
+    initial_state: State,
+    /// The changeable slots in the order they're decided, with the options for each
+    slots: Vec<Slot>,
+    /// For each depth, the most and least that the slots decided from that depth on could add
+    remaining_max: Vec<Characteristic>,
+    remaining_min: Vec<Characteristic>,
+    /// For each depth, the sets that the slots decided from that depth on could add items of
+    remaining_sets: Vec<Vec<SetIndex>>,
+    /// The most and least each set's bonus could give
+    set_bounds: BTreeMap<SetIndex, (Characteristic, Characteristic)>,
+    node_limit: u64,
+}
+
+struct Slot {
+    slot: usize,
+    /// Best looking first, so good sets are found early and more can be skipped
+    options: Vec<Option<ItemIndex>>,
+    /// An earlier slot of the same type. Swapping the items of the two makes the same set, so
+    /// only one order is searched.
+    same_as: Option<usize>,
+}
+
+impl<'a> ExactSolver<'a> {
+    pub fn new(config: &'a Config, items: &'a Items) -> Result<ExactSolver<'a>, OptimiseError> {
+        if config.consider_characteristics {
+            return Err(OptimiseError::ExactSolverUnsupported(
+                "searching characteristic points",
+            ));
+        }
+        let negative_weight = config
+            .damaging_moves
+            .iter()
+            .map(|damaging_move| damaging_move.weight)
+            .chain([config.weapon_hit_weight])
+            .chain(
+                config
+                    .survivability
+                    .as_ref()
+                    .map(|survivability| survivability.weight),
+            )
+            .any(|weight| weight < 0.);
+        if negative_weight {
+            return Err(OptimiseError::ExactSolverUnsupported(
+                "negative damaging move, weapon hit or survivability weights",
+            ));
+        }
+
+        let mut initial_state =
+            State::new_from_initial_equipment(config.initial_set.map(NicheItemIndex::get), items)?;
+        let item_list = AllowedItemCache::new(config, items);
+
+        // the weapon decides the weapon hits, so knowing it early makes for better bounds
+        let mut slot_order = config.changable.clone();
+        slot_order.sort_by_key(|&slot| (slot != WEAPON_SLOT, slot));
+        slot_order.dedup();
+
+        let item_value = |item: ItemIndex| {
+            items[item]
+                .stats
+                .iter()
+                .zip(config.weights)
+                .map(|(&stat, weight)| stat as f64 * weight)
+                .sum::<f64>()
+        };
+
+        let mut slots: Vec<Slot> = Vec::with_capacity(slot_order.len());
+        for &slot in &slot_order {
+            initial_state.set_item(slot, None, items);
+
+            let item_type = slot_index_to_item_type(slot);
+            let mut options = item_list[item_type]
+                .iter()
+                .copied()
+                .map(Some)
+                .collect::<Vec<_>>();
+            options.sort_by(|a, b| {
+                let value = |item: &Option<ItemIndex>| item.map_or(0., item_value);
+                value(b).total_cmp(&value(a))
+            });
+            options.push(None);
+
+            let interchangeable = matches!(item_type, ItemType::Ring | ItemType::Dofus)
+                && config.changed_item_weight == 0.;
+            let same_as = slots
+                .iter()
+                .rposition(|other| slot_index_to_item_type(other.slot) == item_type)
+                .filter(|_| interchangeable);
+
+            slots.push(Slot {
+                slot,
+                options,
+                same_as,
+            });
+        }
+
+        let mut set_bounds = BTreeMap::new();
+        let mut set_bound = |set: SetIndex| {
+            set_bounds.entry(set).or_insert_with(|| {
+                let mut max = Characteristic::new();
+                let mut min = Characteristic::new();
+                for bonus in (1..=16).filter_map(|number_of_items| items[set].get(number_of_items))
+                {
+                    for ((max, min), &stat) in max.iter_mut().zip(min.iter_mut()).zip(bonus.iter())
+                    {
+                        *max = (*max).max(stat);
+                        *min = (*min).min(stat);
+                    }
+                }
+                (max, min)
+            });
+        };
+
+        let mut remaining_max = vec![Characteristic::new(); slots.len() + 1];
+        let mut remaining_min = vec![Characteristic::new(); slots.len() + 1];
+        let mut remaining_sets = vec![Vec::new(); slots.len() + 1];
+        for (depth, slot) in slots.iter().enumerate().rev() {
+            let mut max = remaining_max[depth + 1].clone();
+            let mut min = remaining_min[depth + 1].clone();
+            let mut sets = remaining_sets[depth + 1].clone();
+
+            let mut slot_max = Characteristic::new();
+            let mut slot_min = Characteristic::new();
+            for item in slot.options.iter().flatten().map(|&item| &items[item]) {
+                for ((max, min), &stat) in slot_max
+                    .iter_mut()
+                    .zip(slot_min.iter_mut())
+                    .zip(item.stats.iter())
+                {
+                    *max = (*max).max(stat);
+                    *min = (*min).min(stat);
+                }
+
+                if let Some(set) = item.set_id {
+                    set_bound(set);
+                    if !sets.contains(&set) {
+                        sets.push(set);
+                    }
+                }
+            }
+
+            max += &slot_max;
+            min += &slot_min;
+            remaining_max[depth] = max;
+            remaining_min[depth] = min;
+            remaining_sets[depth] = sets;
+        }
+
+        for set in initial_state
+            .set()
+            .flatten()
+            .filter_map(|item| items[item].set_id)
+        {
+            set_bound(set);
+        }
+
+        Ok(ExactSolver {
+            config,
+            items,
+            initial_state,
+            slots,
+            remaining_max,
+            remaining_min,
+            remaining_sets,
+            set_bounds,
+            node_limit: DEFAULT_NODE_LIMIT,
+        })
+    }
+
+    /// The most partial sets looked at before giving up with `OptimiseError::SearchSpaceTooLarge`
+    pub fn with_node_limit(self, node_limit: u64) -> Self {
+        ExactSolver { node_limit, ..self }
+    }
+
+    /// The set with the lowest energy, along with that energy
+    pub fn solve(&self) -> Result<(State, f64), OptimiseError> {
+        let mut search = Search {
+            best: None,
+            nodes: 0,
+        };
+
+        let mut state = self.initial_state.clone();
+        self.search(&mut state, 0, &mut search)?;
+
+        Ok(search
+            .best
+            .expect("the search always reaches at least one full set"))
+    }
+
+    fn search(
+        &self,
+        state: &mut State,
+        depth: usize,
+        search: &mut Search,
+    ) -> Result<(), OptimiseError> {
+        search.nodes += 1;
+        if search.nodes > self.node_limit {
+            return Err(OptimiseError::SearchSpaceTooLarge(self.node_limit));
+        }
+
+        let Some(slot) = self.slots.get(depth) else {
+            let sets = state.sets(self.items);
+            let energy = state.energy(self.config, self.items, &sets);
+            if search.best.as_ref().is_none_or(|(_, best)| energy < *best) {
+                search.best = Some((state.clone(), energy));
+            }
+
+            return Ok(());
+        };
+
+        if let Some((_, best)) = &search.best {
+            if self.bound(state, depth) >= *best {
+                return Ok(());
+            }
+        }
+
+        let lowest = slot
+            .same_as
+            .and_then(|same_as| state.item(self.slots[same_as].slot));
+
+        for &option in &slot.options {
+            // None counts as the lowest item, so it's only allowed if the other slot is empty too
+            if option < lowest {
+                continue;
+            }
+
+            state.set_item(slot.slot, option, self.items);
+            self.search(state, depth + 1, search)?;
+        }
+
+        state.set_item(slot.slot, None, self.items);
+        Ok(())
+    }
+
+    /// The lowest energy any set could have that keeps the slots decided so far
+    fn bound(&self, state: &State, depth: usize) -> f64 {
+        // the root is never bounded, and the weapon is decided first, so weapon hits are known
+        let mut max = state.item_totals().clone();
+        max += &self.remaining_max[depth];
+        let mut min = state.item_totals().clone();
+        min += &self.remaining_min[depth];
+
+        let decided_sets = state
+            .set()
+            .flatten()
+            .filter_map(|item| self.items[item].set_id)
+            .filter(|set| !self.remaining_sets[depth].contains(set));
+        let mut counted = Vec::new();
+        for set in self.remaining_sets[depth]
+            .iter()
+            .copied()
+            .chain(decided_sets)
+        {
+            if counted.contains(&set) {
+                continue;
+            }
+            counted.push(set);
+
+            let (set_max, set_min) = &self.set_bounds[&set];
+            max += set_max;
+            min += set_min;
+        }
+
+        let max = state.stats_from_totals(self.config, max);
+        let min = state.stats_from_totals(self.config, min);
+
+        // stats with a negative weight are best as low as possible
+        let mut weighted = max.clone();
+        for ((stat, &min), weight) in weighted.iter_mut().zip(min.iter()).zip(self.config.weights) {
+            if weight < 0. {
+                *stat = min;
+            }
+        }
+
+        // every slot still to be decided could change, or stay the same when changes are penalised
+        let decided = self.slots[..depth].iter().map(|slot| slot.slot);
+        let mut changed_items = state.changed_items(self.config, decided);
+        if self.config.changed_item_weight > 0. {
+            changed_items += self.slots.len() - depth;
+        }
+
+        -stat_value(self.config, &weighted)
+            - combat_value(self.config, &max, state.weapon(self.items))
+            - changed_items as f64 * self.config.changed_item_weight
+    }
+}
+
+struct Search {
+    best: Option<(State, f64)>,
+    nodes: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use dofus_characteristics::Stat;
+    use dofus_items::ITEMS;
+
+    use super::*;
+    use crate::config::test_config;
+
+    fn config(changable: Vec<usize>) -> Config {
+        let mut weights = [0.0; 51];
+        weights[Stat::Vitality as usize] = 1.0;
+        weights[Stat::Strength as usize] = 2.0;
+        weights[Stat::AP as usize] = 400.0;
+
+        Config {
+            max_level: 60,
+            weights,
+            changable,
+            ..test_config()
+        }
+    }
+
+    #[test]
+    fn finds_the_same_set_as_trying_everything() {
+        // with a weight on changed items, the slots still to be decided add to the bound too
+        for changed_item_weight in [0., 300.] {
+            let mut config = Config {
+                changed_item_weight,
+                ..config(vec![0, 2])
+            };
+            let allowed = AllowedItemCache::new(&config, &ITEMS);
+            config.initial_set[0] = NicheItemIndex::new(allowed[ItemType::Hat].first().copied());
+
+            let (_, energy) = ExactSolver::new(&config, &ITEMS).unwrap().solve().unwrap();
+
+            let options = |item_type| {
+                allowed[item_type]
+                    .iter()
+                    .copied()
+                    .map(Some)
+                    .chain(std::iter::once(None))
+            };
+
+            let mut state = State::new_from_initial_equipment(
+                config.initial_set.map(NicheItemIndex::get),
+                &ITEMS,
+            )
+            .unwrap();
+            let mut lowest = f64::INFINITY;
+            for hat in options(ItemType::Hat) {
+                state.set_item(0, hat, &ITEMS);
+                for amulet in options(ItemType::Amulet) {
+                    state.set_item(2, amulet, &ITEMS);
+                    let sets = state.sets(&ITEMS);
+                    lowest = lowest.min(state.energy(&config, &ITEMS, &sets));
+                }
+            }
+
+            assert_eq!(energy, lowest);
+        }
+    }
+
+    #[test]
+    fn penalised_changes_only_count_the_decided_slots() {
+        let config = Config {
+            max_level: 20,
+            ..config(vec![0, 1, 2])
+        };
+        let allowed = AllowedItemCache::new(&config, &ITEMS);
+
+        // starting from the best cape and amulet with no hat, so that changing nothing is only
+        // found if the slots still to be decided aren't counted as changed
+        let (best, _) = ExactSolver::new(&config, &ITEMS).unwrap().solve().unwrap();
+        let mut config = Config {
+            changed_item_weight: -10_000.,
+            ..config
+        };
+        config.initial_set[1] = NicheItemIndex::new(best.item(1));
+        config.initial_set[2] = NicheItemIndex::new(best.item(2));
+
+        let (_, energy) = ExactSolver::new(&config, &ITEMS).unwrap().solve().unwrap();
+
+        let options = |item_type| {
+            allowed[item_type]
+                .iter()
+                .copied()
+                .map(Some)
+                .chain(std::iter::once(None))
+        };
+
+        let mut state =
+            State::new_from_initial_equipment(config.initial_set.map(NicheItemIndex::get), &ITEMS)
+                .unwrap();
+        let mut lowest = f64::INFINITY;
+        for hat in options(ItemType::Hat) {
+            state.set_item(0, hat, &ITEMS);
+            for cape in options(ItemType::Cloak) {
+                state.set_item(1, cape, &ITEMS);
+                for amulet in options(ItemType::Amulet) {
+                    state.set_item(2, amulet, &ITEMS);
+                    let sets = state.sets(&ITEMS);
+                    lowest = lowest.min(state.energy(&config, &ITEMS, &sets));
+                }
+            }
+        }
+
+        assert_eq!(energy, lowest);
+    }
+
+    #[test]
+    fn refuses_what_it_cant_bound() {
+        let characteristics = Config {
+            consider_characteristics: true,
+            ..config(vec![0])
+        };
+        let negative_weight = Config {
+            weapon_hit_weight: -1.,
+            ..config(vec![0])
+        };
+
+        for config in [characteristics, negative_weight] {
+            assert!(matches!(
+                ExactSolver::new(&config, &ITEMS),
+                Err(OptimiseError::ExactSolverUnsupported(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn gives_up_on_large_searches() {
+        let config = config((0..16).collect());
+        let solver = ExactSolver::new(&config, &ITEMS)
+            .unwrap()
+            .with_node_limit(1000);
+
+        assert!(matches!(
+            solver.solve(),
+            Err(OptimiseError::SearchSpaceTooLarge(1000))
+        ));
+    }
+}
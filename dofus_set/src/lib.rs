@@ -1,5 +1,9 @@
 #![deny(clippy::all)]
 
-mod anneal;
+pub mod anneal;
+pub mod characteristic_points;
 pub mod config;
+pub mod damage;
 pub mod dofus_set;
+pub mod exact;
+pub mod pareto;
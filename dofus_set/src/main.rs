@@ -2,13 +2,31 @@
 
 use ::dofus_set::{
     config,
-    dofus_set::{Optimiser, State},
+    dofus_set::{Optimiser, ParallelOptions, State},
 };
 use dofus_characteristics::{Characteristic, Stat};
 use dofus_items::{Item, Items, NicheItemIndex, ITEMS};
 
 fn main() {
-    let items = &ITEMS;
+    // an optional DofusLab data directory can be given to use instead of the compiled in items,
+    // and spells to value the damage of with `--spell Class:Spell`
+    let mut directory = None;
+    let mut spells = Vec::new();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--spell" => spells.push(
+                args.next()
+                    .expect("--spell needs a class and spell, like Iop:Pressure"),
+            ),
+            _ => directory = Some(arg),
+        }
+    }
+
+    let loaded_items = directory.map(|directory| {
+        Items::load_dofus_lab(directory).unwrap_or_else(|e| panic!("Failed to load items: {e}"))
+    });
+    let items = loaded_items.as_ref().unwrap_or(&ITEMS);
 
     let mut weights = [0.0; 51];
     weights[Stat::Power as usize] = 1.0;
@@ -17,7 +35,7 @@ fn main() {
     weights[Stat::MP as usize] = 300.0;
     weights[Stat::Range as usize] = 5.0;
 
-    let config = config::Config {
+    let mut config = config::Config {
         max_level: 148,
         weights,
         targets: [None; 51],
@@ -30,12 +48,48 @@ fn main() {
         initial_set: [NicheItemIndex::new(None); 16],
         changed_item_weight: 0.,
         damaging_moves: Vec::new(),
+        weapon_hit_weight: 0.,
+        target: Default::default(),
+        survivability: None,
+        count_steal_heals: false,
         consider_characteristics: false,
+        scrolls: [0; 6],
+        characteristic_rules: Default::default(),
+        class: None,
     };
 
+    for spell in spells {
+        let (class, spell) = spell
+            .split_once(':')
+            .unwrap_or_else(|| panic!("{spell} should be a class and spell, like Iop:Pressure"));
+        let reference = config::SpellReference {
+            class: Some(class.to_owned()),
+            spell: spell.to_owned(),
+            level: None,
+            reach: Default::default(),
+        };
+
+        let damage = config
+            .spell(&reference)
+            .unwrap_or_else(|e| panic!("Failed to look up {spell}: {e}"));
+        config
+            .damaging_moves
+            .push(config::DamagingMovesOptimisation { weight: 1., damage });
+    }
+
     let optimiser = Optimiser::new(&config, 1000., items).unwrap();
 
-    let final_state = optimiser.optimise(1_000_000).unwrap();
+    let chains = std::thread::available_parallelism().map_or(1, |x| x.get());
+    let final_state = optimiser
+        .optimise_parallel(
+            1_000_000,
+            &ParallelOptions {
+                chains,
+                exchanges: 4,
+            },
+        )
+        .unwrap()
+        .best;
     print_state(&final_state, &config, items);
     let sets = final_state.sets(items);
     println!("Set Energy: {}", -final_state.energy(&config, items, &sets));
@@ -45,7 +99,7 @@ pub fn print_state(state: &State, config: &config::Config, items: &Items) {
     let mut last_state_name = "";
 
     for item in state.set().flatten().map(|idx| &items[idx]) {
-        let state_name = item.item_type;
+        let state_name: &str = &item.item_type;
         if state_name != last_state_name {
             println!("{state_name}");
             println!("-----------------------------");
@@ -0,0 +1,375 @@
+//! Finds sets that trade several objectives off against each other, rather than collapsing
+//! them into a single energy with hand picked weights.
+//!
+//! The front is approximated by annealing with many different weightings of the objectives and
+//! keeping every valid set found that no other set beats on every objective at once.
+
+use std::ops::ControlFlow;
+
+use dofus_characteristics::Characteristic;
+use dofus_items::Items;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::Deserialize;
+
+use crate::{
+    anneal::{Observer, Progress},
+    config::{Config, SurvivabilityOptimisation},
+    dofus_set::{effective_health, move_damage, stat_value, OptimiseError, Optimiser, State},
+};
+
+/// Roughly the energy each objective is scaled to, so that no objective drowns out the others
+/// and the restrictions still matter
+const OBJECTIVE_SCALE: f64 = 1000.;
+
+/// How many times during a run the best set so far is offered to the front
+const SNAPSHOTS: i64 = 50;
+
+/// Something to make as large as possible
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum Objective {
+    /// A single stat, by its index in `Characteristic`
+    Stat { stat: usize },
+    /// The expected damage of one of the config's damaging moves, by its index
+    Damage { damaging_move: usize },
+    /// The effective health with the config's survivability options, or against an even mix
+    /// of elements if there aren't any
+    EffectiveHealth,
+    /// The stats weighted by the config's weights
+    WeightedStats,
+}
+
+impl Objective {
+    fn check(&self, index: usize, config: &Config) -> Result<(), OptimiseError> {
+        let valid = match *self {
+            Objective::Stat { stat } => stat < config.weights.len(),
+            Objective::Damage { damaging_move } => damaging_move < config.damaging_moves.len(),
+            Objective::EffectiveHealth | Objective::WeightedStats => true,
+        };
+
+        if valid {
+            Ok(())
+        } else {
+            Err(OptimiseError::InvalidObjective(index))
+        }
+    }
+
+    pub fn value(&self, config: &Config, stats: &Characteristic) -> f64 {
+        match *self {
+            Objective::Stat { stat } => stats.iter().nth(stat).copied().unwrap_or(0) as f64,
+            Objective::Damage { damaging_move } => {
+                move_damage(stats, &config.damaging_moves[damaging_move].damage, config)
+            }
+            Objective::EffectiveHealth => {
+                effective_health(stats, config.max_level, &survivability(config))
+            }
+            Objective::WeightedStats => stat_value(config, stats),
+        }
+    }
+
+    /// Makes the config value this objective by the given weight on top of what it already does
+    fn add_weight(&self, run: &mut Config, original: &Config, weight: f64) {
+        match *self {
+            Objective::Stat { stat } => run.weights[stat] += weight,
+            Objective::Damage { damaging_move } => {
+                run.damaging_moves[damaging_move].weight += weight
+            }
+            Objective::EffectiveHealth => {
+                // the original weight is only there for the config's own energy
+                let mut survivability =
+                    run.survivability
+                        .take()
+                        .unwrap_or_else(|| SurvivabilityOptimisation {
+                            weight: 0.,
+                            ..survivability(original)
+                        });
+                survivability.weight += weight;
+                run.survivability = Some(survivability);
+            }
+            Objective::WeightedStats => {
+                for (run_weight, original_weight) in run.weights.iter_mut().zip(original.weights) {
+                    *run_weight += original_weight * weight;
+                }
+            }
+        }
+    }
+}
+
+fn survivability(config: &Config) -> SurvivabilityOptimisation {
+    config
+        .survivability
+        .clone()
+        .unwrap_or(SurvivabilityOptimisation {
+            weight: 0.,
+            element_mix: [1.; 5],
+            incoming_hit: 100.,
+            melee_share: None,
+            critical: None,
+        })
+}
+
+#[derive(Clone, Debug)]
+pub struct ParetoOptions {
+    pub objectives: Vec<Objective>,
+    /// The number of differently weighted runs, on top of one run for each objective alone
+    pub runs: usize,
+    /// The iterations of each run
+    pub iterations: i64,
+    pub seed: u64,
+}
+
+/// A set on the front, along with the value of each objective in the order they were given
+#[derive(Clone, Debug)]
+pub struct ParetoSet {
+    pub state: State,
+    pub values: Vec<f64>,
+}
+
+impl ParetoSet {
+    /// At least as good on every objective and better on at least one
+    fn dominates(&self, other: &[f64]) -> bool {
+        self.values.iter().zip(other).all(|(a, b)| a >= b)
+            && self.values.iter().zip(other).any(|(a, b)| a > b)
+    }
+}
+
+/// The valid sets that no other set found beats on every objective, sorted by the first
+/// objective best first.
+///
+/// Only the objectives are optimised, so the weights, damaging move weights and changed item
+/// weight of the config are ignored unless an objective uses them.
+pub fn pareto_front(
+    config: &Config,
+    items: &Items,
+    options: &ParetoOptions,
+) -> Result<Vec<ParetoSet>, OptimiseError> {
+    for (index, objective) in options.objectives.iter().enumerate() {
+        objective.check(index, config)?;
+    }
+    if options.objectives.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let mut front = ParetoFront {
+        config,
+        items,
+        objectives: &options.objectives,
+        interval: (options.iterations / SNAPSHOTS).max(1),
+        sets: Vec::new(),
+    };
+    let mut rng = StdRng::seed_from_u64(options.seed);
+
+    // each objective alone finds the ends of the front, and how large each objective can get
+    let mut scales = Vec::with_capacity(options.objectives.len());
+    for i in 0..options.objectives.len() {
+        let mut weights = vec![0.; options.objectives.len()];
+        weights[i] = 1.;
+
+        front.run(&weights, options.iterations, rng.gen())?;
+        let best = front
+            .sets
+            .iter()
+            .map(|set| set.values[i].abs())
+            .fold(0., f64::max);
+        scales.push(OBJECTIVE_SCALE / best.max(1.));
+    }
+
+    for _ in 0..options.runs {
+        // uniformly random over all the ways to split the weight between the objectives
+        let mut weights = options
+            .objectives
+            .iter()
+            .map(|_| -rng.gen_range(f64::EPSILON..1.).ln())
+            .collect::<Vec<_>>();
+        let total = weights.iter().sum::<f64>();
+        for (weight, scale) in weights.iter_mut().zip(&scales) {
+            *weight *= scale / total;
+        }
+
+        front.run(&weights, options.iterations, rng.gen())?;
+    }
+
+    let mut sets = front.sets;
+    sets.sort_by(|a, b| b.values[0].total_cmp(&a.values[0]));
+    Ok(sets)
+}
+
+/// A config that values only the objectives, each by its weight
+fn weighted_config(config: &Config, objectives: &[Objective], weights: &[f64]) -> Config {
+    let mut run = config.clone();
+    run.weights = [0.; 51];
+    run.damaging_moves
+        .iter_mut()
+        .for_each(|damaging_move| damaging_move.weight = 0.);
+    run.weapon_hit_weight = 0.;
+    run.survivability = None;
+    run.changed_item_weight = 0.;
+
+    for (objective, &weight) in objectives.iter().zip(weights) {
+        objective.add_weight(&mut run, config, weight);
+    }
+
+    run
+}
+
+/// The non-dominated valid sets seen so far
+struct ParetoFront<'a> {
+    config: &'a Config,
+    items: &'a Items,
+    objectives: &'a [Objective],
+    interval: i64,
+    sets: Vec<ParetoSet>,
+}
+
+impl ParetoFront<'_> {
+    fn run(&mut self, weights: &[f64], iterations: i64, seed: u64) -> Result<(), OptimiseError> {
+        let run = weighted_config(self.config, self.objectives, weights);
+        let optimiser = Optimiser::new(&run, 1000., self.items)?
+            .with_seed(seed)
+            .with_estimated_initial_temperature();
+        let annealed = optimiser.optimise_observed(iterations, self)?;
+        self.offer(&annealed.best);
+
+        Ok(())
+    }
+
+    fn offer(&mut self, state: &State) {
+        let sets = state.sets(self.items);
+        let stats = state.stats(self.config, &sets);
+        if !state.is_valid(self.config, &stats, self.items, &sets) {
+            return;
+        }
+
+        let values = self
+            .objectives
+            .iter()
+            .map(|objective| objective.value(self.config, &stats))
+            .collect::<Vec<_>>();
+
+        // sets with the same values are the same as far as the front is concerned
+        if self
+            .sets
+            .iter()
+            .any(|set| set.values == values || set.dominates(&values))
+        {
+            return;
+        }
+
+        let set = ParetoSet {
+            state: state.clone(),
+            values,
+        };
+        self.sets.retain(|other| !set.dominates(&other.values));
+        self.sets.push(set);
+    }
+}
+
+impl Observer<State> for ParetoFront<'_> {
+    fn interval(&self) -> i64 {
+        self.interval
+    }
+
+    fn progress(&mut self, progress: &Progress<State>) -> ControlFlow<()> {
+        self.offer(progress.best);
+        ControlFlow::Continue(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use dofus_characteristics::Stat;
+    use dofus_items::ITEMS;
+
+    use super::*;
+    use crate::config::test_config;
+
+    fn config(changable: Vec<usize>) -> Config {
+        Config {
+            max_level: 100,
+            changable,
+            ..test_config()
+        }
+    }
+
+    #[test]
+    fn no_set_on_the_front_dominates_another() {
+        let config = config((0..16).collect());
+
+        let front = pareto_front(
+            &config,
+            &ITEMS,
+            &ParetoOptions {
+                objectives: vec![
+                    Objective::Stat {
+                        stat: Stat::Vitality as usize,
+                    },
+                    Objective::Stat {
+                        stat: Stat::Strength as usize,
+                    },
+                ],
+                runs: 2,
+                iterations: 5_000,
+                seed: 1,
+            },
+        )
+        .unwrap();
+
+        assert!(!front.is_empty());
+        for a in &front {
+            for b in &front {
+                assert!(!a.dominates(&b.values));
+            }
+        }
+        assert!(front
+            .windows(2)
+            .all(|pair| pair[0].values[0] >= pair[1].values[0]));
+    }
+
+    #[test]
+    fn objectives_must_exist() {
+        let config = config(Vec::new());
+
+        let result = pareto_front(
+            &config,
+            &ITEMS,
+            &ParetoOptions {
+                objectives: vec![
+                    Objective::EffectiveHealth,
+                    Objective::Damage { damaging_move: 0 },
+                ],
+                runs: 1,
+                iterations: 100,
+                seed: 1,
+            },
+        );
+        assert!(matches!(result, Err(OptimiseError::InvalidObjective(1))));
+    }
+
+    #[test]
+    fn unweighted_effective_health_changes_nothing() {
+        let config = Config {
+            survivability: Some(SurvivabilityOptimisation {
+                weight: 5.,
+                ..survivability(&test_config())
+            }),
+            ..config((0..16).collect())
+        };
+        let vitality = Objective::Stat {
+            stat: Stat::Vitality as usize,
+        };
+        let state = State::new_from_initial_equipment([None; 16], &ITEMS).unwrap();
+        let sets = state.sets(&ITEMS);
+
+        let with = weighted_config(
+            &config,
+            &[vitality.clone(), Objective::EffectiveHealth],
+            &[1., 0.],
+        );
+        let without = weighted_config(&config, &[vitality], &[1.]);
+
+        assert_eq!(
+            state.energy(&with, &ITEMS, &sets),
+            state.energy(&without, &ITEMS, &sets)
+        );
+    }
+}
@@ -23,12 +23,19 @@ pub fn bench(items: &Items) -> f64 {
         initial_set: [const { NicheItemIndex::new(None) }; 16],
         changed_item_weight: 0.,
         damaging_moves: Vec::new(),
+        weapon_hit_weight: 0.,
+        target: Default::default(),
+        survivability: None,
+        count_steal_heals: false,
         consider_characteristics: false,
+        scrolls: [0; 6],
+        characteristic_rules: Default::default(),
+        class: None,
     };
 
     let optimiser = Optimiser::new(&config, 1000., items).unwrap();
 
-    let final_state = optimiser.optimise(1_000_000).unwrap();
+    let final_state = optimiser.optimise(1_000_000).unwrap().best;
     let sets = final_state.sets(items);
 
     -final_state.energy(&config, items, &sets)
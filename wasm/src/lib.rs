@@ -1,12 +1,29 @@
-use dofus_items::ITEMS;
+use std::{cell::RefCell, rc::Rc};
+
+use dofus_items::{DofusLabData, DofusLabError, Items, ITEMS};
 use dofus_set::dofus_set::OptimiseError;
-use query::OptimiseRequest;
+use query::{DiverseRequest, OptimiseRequest, ParetoRequest};
 use thiserror::Error;
 use wasm_bindgen::prelude::*;
 
 mod benchmark;
 mod query;
 
+thread_local! {
+    /// Items loaded by `load_items`, used instead of the compiled in `ITEMS` once there are any
+    static LOADED_ITEMS: RefCell<Option<Rc<Items>>> = const { RefCell::new(None) };
+}
+
+/// Calls `f` with the loaded items, or the compiled in `ITEMS` if none have been loaded. The
+/// items are held for the whole call, so loading more from a progress callback can't pull them
+/// out from under a running optimisation.
+fn with_items<R>(f: impl FnOnce(&Items) -> R) -> R {
+    match LOADED_ITEMS.with_borrow(Option::clone) {
+        Some(items) => f(&items),
+        None => f(&ITEMS),
+    }
+}
+
 #[wasm_bindgen]
 pub fn setup() {
     console_error_panic_hook::set_once();
@@ -14,7 +31,41 @@ pub fn setup() {
 
 #[wasm_bindgen]
 pub fn benchmark() -> f64 {
-    benchmark::bench(&ITEMS)
+    with_items(benchmark::bench)
+}
+
+#[derive(Error, Debug)]
+pub enum LoadItemsError {
+    #[error("Could not load items, {0}")]
+    DofusLab(#[from] DofusLabError),
+}
+
+impl From<LoadItemsError> for JsValue {
+    fn from(value: LoadItemsError) -> Self {
+        JsValue::from_str(&value.to_string())
+    }
+}
+
+/// Replaces the compiled in items with ones built from the contents of DofusLab's JSON files,
+/// for every later call. Item indexes given out before refer to the old items.
+#[wasm_bindgen]
+pub fn load_items(
+    items: &[u8],
+    weapons: &[u8],
+    mounts: &[u8],
+    pets: &[u8],
+    sets: &[u8],
+) -> Result<(), LoadItemsError> {
+    let items = Items::from_dofus_lab(&DofusLabData {
+        items,
+        weapons,
+        mounts,
+        pets,
+        sets,
+    })?;
+    LOADED_ITEMS.set(Some(Rc::new(items)));
+
+    Ok(())
 }
 
 #[derive(Error, Debug)]
@@ -23,20 +74,54 @@ pub enum QueryError {
     Optimise(#[from] OptimiseError),
     #[error("Could not parse query, {0}")]
     Decode(#[from] serde_wasm_bindgen::Error),
+    /// The progress callback threw, which is passed on as it was thrown
+    #[error("Progress callback failed, {0:?}")]
+    Progress(JsValue),
 }
 
 impl From<QueryError> for JsValue {
     fn from(value: QueryError) -> Self {
-        JsValue::from_str(&value.to_string())
+        match value {
+            QueryError::Progress(error) => error,
+            value => JsValue::from_str(&value.to_string()),
+        }
     }
 }
 
+/// Optimises a set. `progress` is called every `progressInterval` iterations with the best set so
+/// far, and can return `false` to stop early. Anything it throws stops the optimisation and is
+/// thrown on from here.
 #[wasm_bindgen]
-pub fn query(request: JsValue) -> Result<JsValue, QueryError> {
+pub fn query(request: JsValue, progress: Option<js_sys::Function>) -> Result<JsValue, QueryError> {
     let request: OptimiseRequest = serde_wasm_bindgen::from_value(request)?;
-    let response = query::create_optimised_set(&request, &ITEMS)?;
+    with_items(|items| {
+        let response = query::create_optimised_set(&request, items, progress.as_ref())?;
+
+        Ok(serde_wasm_bindgen::to_value(&response)?)
+    })
+}
+
+/// Optimises several sets that are different enough to be worth comparing, returned best first
+#[wasm_bindgen]
+pub fn query_diverse(request: JsValue) -> Result<JsValue, QueryError> {
+    let request: DiverseRequest = serde_wasm_bindgen::from_value(request)?;
+    with_items(|items| {
+        let response = query::create_diverse_sets(&request, items)?;
+
+        Ok(serde_wasm_bindgen::to_value(&response)?)
+    })
+}
 
-    Ok(serde_wasm_bindgen::to_value(&response)?)
+/// Optimises sets that trade several objectives off against each other, so none of them is
+/// better than another at every objective
+#[wasm_bindgen]
+pub fn query_pareto(request: JsValue) -> Result<JsValue, QueryError> {
+    let request: ParetoRequest = serde_wasm_bindgen::from_value(request)?;
+    with_items(|items| {
+        let response = query::create_pareto_front(&request, items)?;
+
+        Ok(serde_wasm_bindgen::to_value(&response)?)
+    })
 }
 
 #[derive(Error, Debug)]
@@ -53,18 +138,53 @@ impl From<ItemsSlotError> for JsValue {
 
 #[wasm_bindgen]
 pub fn items_in_slot(slot: usize) -> Result<JsValue, ItemsSlotError> {
-    let response =
-        query::get_item_list_index(slot, &ITEMS).ok_or(ItemsSlotError::SlotOutOfRange)?;
+    with_items(|items| {
+        let response =
+            query::get_item_list_index(slot, items).ok_or(ItemsSlotError::SlotOutOfRange)?;
 
-    Ok(serde_wasm_bindgen::to_value(&response).expect("Known format"))
+        Ok(serde_wasm_bindgen::to_value(&response).expect("Known format"))
+    })
 }
 
 #[wasm_bindgen]
 pub fn get_all_items() -> JsValue {
-    serde_wasm_bindgen::to_value(&query::get_all_items(&ITEMS)).expect("Known format")
+    with_items(|items| {
+        serde_wasm_bindgen::to_value(&query::get_all_items(items)).expect("Known format")
+    })
 }
 
 #[wasm_bindgen]
 pub fn get_spells() -> JsValue {
     serde_wasm_bindgen::to_value(dofus_items::SPELLS).expect("Known format")
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn loaded_items_replace_the_compiled_items() {
+        let data = |name: &str| {
+            std::fs::read(format!(
+                "{}/../dofus_items/data/{name}",
+                env!("CARGO_MANIFEST_DIR")
+            ))
+            .unwrap()
+        };
+        let only_weapons =
+            || with_items(|items| items.iter().all(|(_, item)| item.weapon.is_some()));
+        assert!(!only_weapons());
+
+        load_items(
+            b"[]",
+            &data("weapons.json"),
+            b"[]",
+            b"[]",
+            &data("sets.json"),
+        )
+        .unwrap();
+
+        assert!(only_weapons());
+        assert!(with_items(|items| items.weapons().count()) > 0);
+    }
+}
@@ -1,10 +1,22 @@
-use dofus_characteristics::Characteristic;
+use std::ops::ControlFlow;
+
+use dofus_characteristics::{Characteristic, Element};
 use dofus_items::{Item, ItemIndex, Items, NicheItemIndex};
 use dofus_set::{
-    config::{Config, DamagingMove, DamagingMovesOptimisation},
-    dofus_set::OptimiseError,
+    anneal::{CoolingSchedule, Observer, Progress},
+    characteristic_points::CharacteristicRules,
+    config::{
+        BaseDamage, Config, DamagingMove, DamagingMovesOptimisation, MoveKind, MoveLine, Reach,
+        SpellReference, SurvivabilityOptimisation, TargetProfile,
+    },
+    dofus_set::{DiverseOptions, MoveWeights, OptimiseError, Optimiser, State},
+    pareto::{pareto_front, Objective, ParetoOptions},
 };
+use rand::Rng;
 use serde::{Deserialize, Serialize};
+use wasm_bindgen::JsValue;
+
+use crate::QueryError;
 
 #[derive(Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
@@ -21,70 +33,127 @@ pub struct OptimiseRequest {
     multi_element: bool,
     changed_item_weight: f64,
     damaging_moves_weights: Vec<DamagingMovesWeight>,
+    #[serde(default)]
+    weapon_hit_weight: f64,
+    #[serde(default)]
+    target: TargetProfile,
+    #[serde(default)]
+    survivability: Option<SurvivabilityOptimisation>,
+    #[serde(default)]
+    count_steal_heals: bool,
+    #[serde(default)]
+    seed: Option<u64>,
+    #[serde(default = "default_progress_interval")]
+    progress_interval: i64,
     iterations: i64,
-    initial_temperature: f64,
+    /// Estimated from the items and weights if not given
+    #[serde(default)]
+    initial_temperature: Option<f64>,
+    #[serde(default)]
+    cooling_schedule: CoolingSchedule,
+    #[serde(default)]
+    move_weights: MoveWeights,
     consider_characteristics: bool,
+    #[serde(default)]
+    scrolls: [i32; 6],
+    /// The rules of the class, or of the current game version without a class, are used if
+    /// these aren't given
+    #[serde(default)]
+    characteristic_rules: Option<CharacteristicRules>,
+    #[serde(default)]
+    class: Option<String>,
+}
+
+fn default_progress_interval() -> i64 {
+    10_000
 }
 
 #[derive(Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 struct DamagingMovesWeight {
     weight: f64,
+    #[serde(flatten)]
+    damage: DamagingMoveDamage,
+}
+
+/// Either a spell to look the damage up from, or the damage itself, told apart by `source`
+#[derive(Deserialize, Debug)]
+#[serde(tag = "source", rename_all = "camelCase")]
+enum DamagingMoveDamage {
+    Spell(SpellReference),
+    Given(GivenDamage),
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct GivenDamage {
     base_damage: [f64; 5],
     base_crit_damage: [f64; 5],
     base_crit_percent: i32,
     crit_modifyable: bool,
+    #[serde(default)]
+    kind: MoveKind,
+    #[serde(default)]
+    reach: Reach,
+    #[serde(default)]
+    heal: bool,
+    #[serde(default)]
+    steal: [bool; 5],
 }
 
 #[derive(Serialize, Debug)]
 #[serde(rename_all = "camelCase")]
-struct OptimiseResponseSetBonus {
-    name: &'static str,
+struct OptimiseResponseSetBonus<'a> {
+    name: &'a str,
     number_of_items: i32,
     characteristics: Characteristic,
 }
 
 #[derive(Serialize, Debug)]
 #[serde(rename_all = "camelCase")]
-pub struct OptimiseResponseItem {
+pub struct OptimiseResponseItem<'a> {
     dofus_id: ItemIndex,
     characteristics: Characteristic,
-    name: &'static str,
-    item_type: &'static str,
+    name: &'a str,
+    item_type: &'a str,
     level: i32,
-    image_url: &'static str,
+    image_url: &'a str,
 }
 
 #[derive(Serialize, Debug)]
 #[serde(rename_all = "camelCase")]
-pub struct OptimiseResponse {
+pub struct OptimiseResponse<'a> {
     energy: f64,
+    /// The energy of the state the optimisation finished on, which can be worse than the best
+    /// state that is returned
+    final_energy: f64,
+    seed: u64,
     overall_characteristics: Characteristic,
-    items: Vec<Option<OptimiseResponseItem>>,
-    set_bonuses: Vec<OptimiseResponseSetBonus>,
+    items: Vec<Option<OptimiseResponseItem<'a>>>,
+    set_bonuses: Vec<OptimiseResponseSetBonus<'a>>,
     valid: bool,
     characteristics: Vec<i32>,
 }
 
-fn make_optimise_response(id: ItemIndex, item: &Item) -> OptimiseResponseItem {
+fn make_optimise_response(id: ItemIndex, item: &Item) -> OptimiseResponseItem<'_> {
     OptimiseResponseItem {
         dofus_id: id,
         characteristics: item.stats.clone(),
-        name: item.name,
-        item_type: item.item_type,
+        name: &item.name,
+        item_type: &item.item_type,
         level: item.level,
-        image_url: item.image_url,
+        image_url: &item.image_url,
     }
 }
 
-fn item_list(list: &[ItemIndex], items: &Items) -> Vec<OptimiseResponseItem> {
+fn item_list<'a>(list: &[ItemIndex], items: &'a Items) -> Vec<OptimiseResponseItem<'a>> {
     list.iter()
         .map(|&x| (x, &items[x]))
         .map(|(a, b)| make_optimise_response(a, b))
         .collect()
 }
 
-pub fn get_item_list_index(slot: usize, items: &Items) -> Option<Vec<OptimiseResponseItem>> {
+pub fn get_item_list_index(slot: usize, items: &Items) -> Option<Vec<OptimiseResponseItem<'_>>> {
     if slot >= 16 {
         return None;
     }
@@ -94,74 +163,305 @@ pub fn get_item_list_index(slot: usize, items: &Items) -> Option<Vec<OptimiseRes
     Some(item_list(&items[item_type], items))
 }
 
-pub fn get_all_items(items: &Items) -> Vec<OptimiseResponseItem> {
+pub fn get_all_items(items: &Items) -> Vec<OptimiseResponseItem<'_>> {
     items
         .iter()
         .map(|(a, b)| make_optimise_response(a, b))
         .collect()
 }
 
-pub fn create_optimised_set(
-    config: &OptimiseRequest,
-    items: &'static Items,
-) -> Result<OptimiseResponse, OptimiseError> {
-    if config.weights.len() != 51 {
-        return Err(OptimiseError::InvalidState);
+impl GivenDamage {
+    fn damaging_move(&self) -> DamagingMove {
+        // given damage is one line per element, like a spell
+        let lines = Element::ALL
+            .into_iter()
+            .filter(|&element| {
+                self.base_damage[element as usize] != 0.
+                    || self.base_crit_damage[element as usize] != 0.
+            })
+            .map(|element| {
+                let normal = self.base_damage[element as usize];
+                let critical = self.base_crit_damage[element as usize];
+                MoveLine {
+                    element,
+                    normal: BaseDamage {
+                        min: normal,
+                        max: normal,
+                    },
+                    critical: BaseDamage {
+                        min: critical,
+                        max: critical,
+                    },
+                    steal: self.steal[element as usize],
+                }
+            })
+            .collect();
+
+        DamagingMove {
+            lines,
+            base_crit_ratio: self.base_crit_percent,
+            modifyable_crit: self.crit_modifyable,
+            kind: self.kind,
+            reach: self.reach,
+            heal: self.heal,
+        }
     }
+}
 
-    let mut weights: [f64; 51] = [0.0f64; 51];
-    weights[..51].clone_from_slice(&config.weights[..51]);
+impl OptimiseRequest {
+    fn config(&self) -> Result<Config, OptimiseError> {
+        if self.weights.len() != 51 {
+            return Err(OptimiseError::InvalidState);
+        }
 
-    let mut targets: [Option<i32>; 51] = [None; 51];
-    targets[..].clone_from_slice(&config.targets);
+        let mut weights: [f64; 51] = [0.0f64; 51];
+        weights[..51].clone_from_slice(&self.weights[..51]);
 
-    let changable = (0..16)
-        .filter(|x| !config.fixed_items.contains(x))
-        .collect();
+        let mut targets: [Option<i32>; 51] = [None; 51];
+        targets[..].clone_from_slice(&self.targets);
 
-    let dofus_set_config = Config {
-        max_level: config.max_level,
-        weights,
-        targets,
-        changable,
-        ban_list: config.banned_items.clone(),
-        exo_ap: config.ap_exo,
-        exo_mp: config.mp_exo,
-        exo_range: config.range_exo,
-        multi_element: config.multi_element,
-        initial_set: config
-            .initial_items
-            .iter()
-            .copied()
-            .map(NicheItemIndex::new)
-            .collect::<Vec<_>>()
-            .try_into()
-            .expect(
-                "should be able to make 16 length initial items from provided initial items list",
-            ),
-        changed_item_weight: config.changed_item_weight,
-        damaging_moves: config
+        let class = self
+            .class
+            .as_deref()
+            .map(|name| {
+                dofus_items::class(name).ok_or_else(|| OptimiseError::UnknownClass(name.to_owned()))
+            })
+            .transpose()?;
+        let characteristic_rules = match (&self.characteristic_rules, class) {
+            (Some(rules), _) => rules.clone(),
+            (None, Some(class)) => CharacteristicRules::for_class(class),
+            (None, None) => CharacteristicRules::default(),
+        };
+        characteristic_rules.validate()?;
+
+        let changable = (0..16).filter(|x| !self.fixed_items.contains(x)).collect();
+
+        let mut config = Config {
+            max_level: self.max_level,
+            weights,
+            targets,
+            changable,
+            ban_list: self.banned_items.clone(),
+            exo_ap: self.ap_exo,
+            exo_mp: self.mp_exo,
+            exo_range: self.range_exo,
+            multi_element: self.multi_element,
+            initial_set: self
+                .initial_items
+                .iter()
+                .copied()
+                .map(NicheItemIndex::new)
+                .collect::<Vec<_>>()
+                .try_into()
+                .expect(
+                    "should be able to make 16 length initial items from provided initial items list",
+                ),
+            changed_item_weight: self.changed_item_weight,
+            damaging_moves: Vec::new(),
+            weapon_hit_weight: self.weapon_hit_weight,
+            target: self.target.clone(),
+            survivability: self.survivability.clone(),
+            count_steal_heals: self.count_steal_heals,
+            consider_characteristics: self.consider_characteristics,
+            scrolls: self.scrolls,
+            characteristic_rules,
+            class,
+        };
+
+        // spells are looked up with the class and level of the rest of the config
+        config.damaging_moves = self
             .damaging_moves_weights
             .iter()
-            .map(|x| DamagingMovesOptimisation {
-                weight: x.weight,
-                damage: DamagingMove {
-                    elemental_damage: x.base_damage,
-                    crit_elemental_damage: x.base_crit_damage,
-                    base_crit_ratio: x.base_crit_percent,
-                    modifyable_crit: x.crit_modifyable,
-                },
+            .map(|x| {
+                Ok(DamagingMovesOptimisation {
+                    weight: x.weight,
+                    damage: match &x.damage {
+                        DamagingMoveDamage::Spell(reference) => config.spell(reference)?,
+                        DamagingMoveDamage::Given(given) => given.damaging_move(),
+                    },
+                })
             })
-            .collect(),
-        consider_characteristics: config.consider_characteristics,
+            .collect::<Result<_, OptimiseError>>()?;
+
+        Ok(config)
+    }
+
+    /// The seed to use, seeds are numbers in JavaScript so random ones are picked to be exactly
+    /// representable
+    fn seed(&self) -> u64 {
+        self.seed
+            .unwrap_or_else(|| rand::thread_rng().gen_range(0..1 << 53))
+    }
+
+    fn optimiser<'a>(
+        &self,
+        config: &'a Config,
+        items: &'a Items,
+        seed: u64,
+    ) -> Result<Optimiser<'a>, OptimiseError> {
+        let optimiser =
+            Optimiser::new(config, self.initial_temperature.unwrap_or_default(), items)?
+                .with_seed(seed)
+                .with_cooling_schedule(self.cooling_schedule)
+                .with_move_weights(self.move_weights.clone());
+
+        Ok(match self.initial_temperature {
+            Some(_) => optimiser,
+            None => optimiser.with_estimated_initial_temperature(),
+        })
+    }
+}
+
+pub fn create_optimised_set<'a>(
+    request: &OptimiseRequest,
+    items: &'a Items,
+    progress: Option<&js_sys::Function>,
+) -> Result<OptimiseResponse<'a>, QueryError> {
+    let report = progress.map(|callback| {
+        move |progress: &OptimiseProgress| {
+            let progress = serde_wasm_bindgen::to_value(progress).expect("Known format");
+            let result = callback.call1(&JsValue::NULL, &progress)?;
+            Ok(result.as_bool() != Some(false))
+        }
+    });
+
+    optimised_set(request, items, report)
+}
+
+/// Optimises a set, calling `report` every `progress_interval` iterations if it's given.
+/// `report` returns whether to carry on, and an error from it ends the optimisation with that
+/// error.
+fn optimised_set<'a, F>(
+    request: &OptimiseRequest,
+    items: &'a Items,
+    report: Option<F>,
+) -> Result<OptimiseResponse<'a>, QueryError>
+where
+    F: FnMut(&OptimiseProgress) -> Result<bool, JsValue>,
+{
+    let config = request.config()?;
+    let seed = request.seed();
+    let optimiser = request.optimiser(&config, items, seed)?;
+
+    let annealed = match report {
+        Some(report) => {
+            let mut observer = ProgressCallback {
+                report,
+                interval: request.progress_interval,
+                config: &config,
+                items,
+                seed,
+                error: None,
+            };
+            let annealed = optimiser.optimise_observed(request.iterations, &mut observer)?;
+            if let Some(error) = observer.error {
+                return Err(QueryError::Progress(error));
+            }
+
+            annealed
+        }
+        None => optimiser.optimise(request.iterations)?,
     };
 
-    let optimiser =
-        dofus_set::dofus_set::Optimiser::new(&dofus_set_config, config.initial_temperature, items)?;
+    Ok(state_response(
+        &annealed.best,
+        -annealed.last_energy,
+        &config,
+        items,
+        seed,
+    ))
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct DiverseRequest {
+    #[serde(flatten)]
+    request: OptimiseRequest,
+    count: usize,
+    min_distance: usize,
+}
+
+/// Several good sets, best first, that each differ from the others by at least `min_distance` items
+pub fn create_diverse_sets<'a>(
+    request: &DiverseRequest,
+    items: &'a Items,
+) -> Result<Vec<OptimiseResponse<'a>>, OptimiseError> {
+    let config = request.request.config()?;
+    let seed = request.request.seed();
+    let optimiser = request.request.optimiser(&config, items, seed)?;
 
-    let final_state = optimiser.optimise(config.iterations)?;
+    let states = optimiser.optimise_diverse(
+        request.request.iterations,
+        &DiverseOptions {
+            count: request.count,
+            min_distance: request.min_distance,
+        },
+    )?;
 
-    let sets = final_state.sets(items);
+    Ok(states
+        .iter()
+        .map(|(state, energy)| state_response(state, -energy, &config, items, seed))
+        .collect())
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ParetoRequest {
+    #[serde(flatten)]
+    request: OptimiseRequest,
+    objectives: Vec<Objective>,
+    runs: usize,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ParetoResponse<'a> {
+    /// The value of each objective in the order they were requested
+    values: Vec<f64>,
+    set: OptimiseResponse<'a>,
+}
+
+/// Sets that trade the objectives off against each other, none better than another at all of them
+pub fn create_pareto_front<'a>(
+    request: &ParetoRequest,
+    items: &'a Items,
+) -> Result<Vec<ParetoResponse<'a>>, OptimiseError> {
+    let config = request.request.config()?;
+    let seed = request.request.seed();
+
+    let front = pareto_front(
+        &config,
+        items,
+        &ParetoOptions {
+            objectives: request.objectives.clone(),
+            runs: request.runs,
+            iterations: request.request.iterations,
+            seed,
+        },
+    )?;
+
+    Ok(front
+        .into_iter()
+        .map(|set| {
+            let sets = set.state.sets(items);
+            let energy = set.state.energy(&config, items, &sets);
+
+            ParetoResponse {
+                set: state_response(&set.state, -energy, &config, items, seed),
+                values: set.values,
+            }
+        })
+        .collect())
+}
+
+fn state_response<'a>(
+    state: &State,
+    final_energy: f64,
+    config: &Config,
+    items: &'a Items,
+    seed: u64,
+) -> OptimiseResponse<'a> {
+    let sets = state.sets(items);
 
     let set_bonuses = sets
         .iter()
@@ -172,29 +472,182 @@ pub fn create_optimised_set(
         })
         .collect();
 
-    let stats = final_state.stats(&dofus_set_config, &sets);
+    let stats = state.stats(config, &sets);
 
-    Ok(OptimiseResponse {
-        energy: -final_state.energy(&dofus_set_config, items, &sets),
-        valid: final_state.is_valid(&dofus_set_config, &stats, items, &sets),
+    OptimiseResponse {
+        energy: -state.energy(config, items, &sets),
+        final_energy,
+        seed,
+        valid: state.is_valid(config, &stats, items, &sets),
         overall_characteristics: stats,
-        items: final_state
+        items: state
             .set()
-            .map(|idx| {
-                idx.map(|idx| {
-                    let item = &items[idx];
-                    OptimiseResponseItem {
-                        dofus_id: idx,
-                        characteristics: item.stats.clone(),
-                        name: item.name,
-                        item_type: item.item_type,
-                        level: item.level,
-                        image_url: item.image_url,
-                    }
-                })
-            })
+            .map(|idx| idx.map(|idx| make_optimise_response(idx, &items[idx])))
             .collect(),
         set_bonuses,
-        characteristics: final_state.points().to_vec(),
-    })
+        characteristics: state.points().to_vec(),
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct OptimiseProgress<'a> {
+    iteration: i64,
+    iterations: i64,
+    temperature: f64,
+    energy: f64,
+    best: OptimiseResponse<'a>,
+}
+
+/// Reports progress to a function, which is usually a JavaScript one, that can return `false` to
+/// stop the optimisation
+struct ProgressCallback<'a, F> {
+    report: F,
+    interval: i64,
+    config: &'a Config,
+    items: &'a Items,
+    seed: u64,
+    /// What the function threw, which stopped the optimisation
+    error: Option<JsValue>,
+}
+
+impl<F> Observer<State> for ProgressCallback<'_, F>
+where
+    F: FnMut(&OptimiseProgress) -> Result<bool, JsValue>,
+{
+    fn interval(&self) -> i64 {
+        self.interval
+    }
+
+    fn progress(&mut self, progress: &Progress<State>) -> ControlFlow<()> {
+        let progress = OptimiseProgress {
+            iteration: progress.iteration,
+            iterations: progress.iterations,
+            temperature: progress.temperature,
+            energy: -progress.energy,
+            best: state_response(
+                progress.best,
+                -progress.energy,
+                self.config,
+                self.items,
+                self.seed,
+            ),
+        };
+
+        match (self.report)(&progress) {
+            Ok(true) => ControlFlow::Continue(()),
+            Ok(false) => ControlFlow::Break(()),
+            Err(error) => {
+                self.error = Some(error);
+                ControlFlow::Break(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use dofus_items::ITEMS;
+    use serde_json::json;
+
+    use super::*;
+
+    fn request(changes: serde_json::Value) -> OptimiseRequest {
+        let mut request = json!({
+            "weights": vec![0.; 51],
+            "targets": vec![None::<i32>; 51],
+            "maxLevel": 200,
+            "initialItems": vec![None::<i32>; 16],
+            "fixedItems": [],
+            "bannedItems": [],
+            "apExo": false,
+            "mpExo": false,
+            "rangeExo": false,
+            "multiElement": false,
+            "changedItemWeight": 0.,
+            "damagingMovesWeights": [],
+            "iterations": 1000,
+            "considerCharacteristics": false,
+        });
+        for (key, value) in changes.as_object().unwrap() {
+            request[key] = value.clone();
+        }
+
+        serde_json::from_value(request).unwrap()
+    }
+
+    #[test]
+    fn the_class_picks_the_characteristic_rules() {
+        let vitality = |request: OptimiseRequest| {
+            request.config().unwrap().characteristic_rules.costs[0].gain(100)
+        };
+
+        assert_eq!(vitality(request(json!({}))), 100);
+        assert_eq!(vitality(request(json!({ "class": "Sacrier" }))), 200);
+
+        let halved = json!([{ "from": 0, "cost": 2, "gain": 1 }]);
+        let given = json!({ "costs": vec![halved; 6] });
+        assert_eq!(
+            vitality(request(json!({
+                "class": "Sacrier",
+                "characteristicRules": given,
+            }))),
+            50
+        );
+    }
+
+    #[test]
+    fn progress_is_reported_every_interval() {
+        let request = request(json!({ "seed": 1, "progressInterval": 300 }));
+
+        let mut reported = Vec::new();
+        let report = |progress: &OptimiseProgress| {
+            reported.push(progress.iteration);
+            Ok(true)
+        };
+        optimised_set(&request, &ITEMS, Some(report)).unwrap();
+        assert_eq!(reported, [0, 300, 600, 900]);
+
+        let mut reported = Vec::new();
+        let report = |progress: &OptimiseProgress| {
+            reported.push(progress.iteration);
+            Ok(progress.iteration < 300)
+        };
+        optimised_set(&request, &ITEMS, Some(report)).unwrap();
+        assert_eq!(reported, [0, 300]);
+    }
+
+    #[test]
+    fn errors_from_the_progress_callback_are_passed_on() {
+        let request = request(json!({ "seed": 1, "progressInterval": 300 }));
+
+        let mut calls = 0;
+        let report = |_: &OptimiseProgress| {
+            calls += 1;
+            Err(JsValue::UNDEFINED)
+        };
+        let result = optimised_set(&request, &ITEMS, Some(report));
+        assert!(matches!(result, Err(QueryError::Progress(_))));
+        assert_eq!(calls, 1);
+    }
+
+    #[test]
+    fn responses_give_the_seed_they_used() {
+        let given = create_optimised_set(&request(json!({ "seed": 42 })), &ITEMS, None).unwrap();
+        assert_eq!(given.seed, 42);
+
+        // a random seed is picked when none is given, which runs the same again when given
+        let picked = create_optimised_set(&request(json!({})), &ITEMS, None).unwrap();
+        let again =
+            create_optimised_set(&request(json!({ "seed": picked.seed })), &ITEMS, None).unwrap();
+        let items = |response: &OptimiseResponse| {
+            response
+                .items
+                .iter()
+                .map(|item| item.as_ref().map(|item| item.dofus_id))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(items(&again), items(&picked));
+        assert_eq!(again.energy, picked.energy);
+    }
 }
@@ -22,8 +22,16 @@ const spellsForSelectedClass = atom(async (get) => {
   if (!_class) return null;
 
   const spells = await get(spellsAtom);
+  const spellClass = spells.find((x) => x.name === _class);
+  if (!spellClass) return null;
 
-  return spells.find((x) => x.name === _class);
+  // spells that never deal damage can't be optimised for
+  return {
+    ...spellClass,
+    spells: spellClass.spells.filter((x) =>
+      x.effects.some((x) => x.normal !== null)
+    ),
+  };
 });
 
 const DamagingMoveGrid = styled.div`
@@ -72,6 +80,9 @@ function DamagingMove({ move }: DamagingMoveProps) {
             setMove((move) => {
               const selectedSpell = Number(evt.target.value);
               move.spell = spellsForClass?.spells.at(selectedSpell) ?? null;
+              move.className = move.spell
+                ? (spellsForClass?.name ?? null)
+                : null;
             })
           }
         >
@@ -121,6 +132,7 @@ export function DamagingMoveInput() {
   const addDamagingMove = useCallback(() => {
     const newDamagingMove = atom<OptimiseDamagingMoveString>({
       weight: 1,
+      className: null,
       spell: null,
     });
     setDamagingMoves((moves) => [...moves, newDamagingMove]);
@@ -2,6 +2,8 @@ import { WorkerQuery } from "./worker";
 
 export interface OptimiseApiResponse {
   energy: number;
+  finalEnergy: number;
+  seed: number;
   overallCharacteristics: number[];
   items: (OptimiseApiResponseItem | null)[];
   setBonuses: OptimiseApiResponseSetBonus[];
@@ -9,6 +11,14 @@ export interface OptimiseApiResponse {
   valid: boolean;
 }
 
+export interface OptimiseApiProgress {
+  iteration: number;
+  iterations: number;
+  temperature: number;
+  energy: number;
+  best: OptimiseApiResponse;
+}
+
 export interface OptimiseApiResponseItem {
   characteristics: number[];
   name: string;
@@ -37,25 +47,82 @@ export interface OptimisationConfig {
   multiElement: boolean;
   changedItemWeight: number;
   damagingMovesWeights: OptimisationDamagingMove[];
+  weaponHitWeight?: number;
+  target?: OptimisationTargetProfile;
+  survivability?: OptimisationSurvivability;
+  countStealHeals?: boolean;
   considerCharacteristics: boolean;
+  scrolls?: number[];
+  characteristicRules?: OptimisationCharacteristicRules;
+  class?: string;
+}
+
+export interface OptimisationCostTier {
+  from: number;
+  cost: number;
+  gain: number;
+}
+
+export interface OptimisationCharacteristicRules {
+  costs?: OptimisationCostTier[][];
+  pointsPerLevel?: number;
+  lastLevelWithPoints?: number;
+}
+
+export interface OptimisationSurvivability {
+  weight: number;
+  elementMix: number[];
+  incomingHit: number;
+  meleeShare?: number;
+  critical?: { chance: number; bonusDamage: number };
+}
+
+export interface OptimisationTargetProfile {
+  resistanceFixed: number[];
+  resistancePercent: number[];
+  resistanceMelee: number;
+  resistanceRanged: number;
+}
+
+export type OptimisationDamagingMove =
+  | OptimisationSpellDamagingMove
+  | OptimisationGivenDamagingMove;
+
+export interface OptimisationSpellDamagingMove {
+  source: "spell";
+  weight: number;
+  class?: string;
+  spell: string;
+  level?: number;
+  reach?: "melee" | "ranged";
 }
 
-export interface OptimisationDamagingMove {
+export interface OptimisationGivenDamagingMove {
+  source: "given";
   weight: number;
   baseDamage: number[];
   baseCritDamage: number[];
   baseCritPercent: number;
   critModifyable: boolean;
+  kind?: "spell" | "weapon";
+  reach?: "melee" | "ranged";
+  heal?: boolean;
+  steal?: boolean[];
 }
 
 export interface OptimisationSettings {
   iterations: number;
-  initialTemperature: number;
+  initialTemperature?: number;
+  coolingSchedule?: OptimisationCoolingSchedule;
+  moveWeights?: OptimisationMoveWeights;
+  seed?: number;
+  progressInterval?: number;
 }
 
 export interface SpellElementDamage {
   min: number;
   max: number;
+  steal: boolean;
 }
 
 export interface SpellDamage {
@@ -86,13 +153,49 @@ export interface SpellClass {
   spells: SpellSpell[];
 }
 
+export type OptimisationCoolingSchedule =
+  | { kind: "quenchedExponential"; quench: number }
+  | { kind: "geometric" }
+  | { kind: "linear" }
+  | { kind: "reheating"; window: number; minAcceptance: number };
+
+export interface OptimisationMoveWeights {
+  item?: number;
+  characteristic?: number;
+  set?: number;
+  ringSwap?: number;
+  dofusSwap?: number;
+}
+
 export type OptimisationRequest = OptimisationConfig & OptimisationSettings;
 
+export type OptimisationDiverseRequest = OptimisationRequest & {
+  count: number;
+  minDistance: number;
+};
+
+export type OptimisationObjective =
+  | { kind: "stat"; stat: number }
+  | { kind: "damage"; damagingMove: number }
+  | { kind: "effectiveHealth" }
+  | { kind: "weightedStats" };
+
+export type OptimisationParetoRequest = OptimisationRequest & {
+  objectives: OptimisationObjective[];
+  runs: number;
+};
+
+export interface OptimiseApiParetoResponse {
+  values: number[];
+  set: OptimiseApiResponse;
+}
+
 interface QueuedJob {
   query: WorkerQuery;
   resolve: (data: unknown) => void;
   reject: (data: unknown) => void;
   abort: AbortSignal;
+  progress?: (data: unknown) => void;
 }
 
 export class Optimiser {
@@ -100,6 +203,7 @@ export class Optimiser {
     [id: string]: {
       resolve: (data: unknown) => void;
       reject: (data: unknown) => void;
+      progress?: (data: unknown) => void;
     };
   } = {};
   private jobQueue: QueuedJob[] = [];
@@ -120,6 +224,11 @@ export class Optimiser {
     const worker = new Worker(new URL("./worker", import.meta.url));
     worker.onmessage = (message) => {
       const id = message.data.id;
+      if ("progress" in message.data) {
+        this.activeJobs[id]?.progress?.(message.data.progress);
+        return;
+      }
+
       console.log("Job resolved", id, message.data.response);
       if (message.data.success) {
         this.activeJobs[id].resolve(message.data.response);
@@ -162,7 +271,14 @@ export class Optimiser {
         return;
       }
       const worker = this.freeWorkers.pop()!; // just checked it is not empty
+      const cancel = job.query.kind === "optimise" ? job.query.cancel : undefined;
       const abortListener = () => {
+        if (cancel) {
+          // the worker stops at its next progress report and sends back the best set so far
+          Atomics.store(cancel, 0, 1);
+          return;
+        }
+
         worker.terminate();
         this.workerCount -= 1;
         this.createWorker();
@@ -181,6 +297,7 @@ export class Optimiser {
       this.activeJobs[job.query.id] = {
         resolve: wrapRemoveListener(job.resolve),
         reject: wrapRemoveListener(job.reject),
+        progress: job.progress,
       };
       worker.postMessage(job.query);
     }
@@ -190,10 +307,11 @@ export class Optimiser {
     query: WorkerQuery,
     resolve: (data: unknown) => void,
     reject: (data: unknown) => void,
-    abort: AbortSignal
+    abort: AbortSignal,
+    progress?: (data: unknown) => void
   ) {
     console.log("Job allocated", query);
-    this.jobQueue.push({ query, resolve, reject, abort });
+    this.jobQueue.push({ query, resolve, reject, abort, progress });
     this.allocateJob();
   }
 
@@ -207,20 +325,32 @@ export class Optimiser {
 
   async optimise(
     options: OptimisationRequest,
-    extra?: { abort?: AbortSignal }
+    extra?: {
+      abort?: AbortSignal;
+      onProgress?: (progress: OptimiseApiProgress) => void;
+    }
   ): Promise<OptimiseApiResponse> {
     return new Promise((resolve, reject) => {
       const jobId = crypto.randomUUID();
+      // with shared memory an abort stops the optimisation early with the best set so far,
+      // without it the worker has to be thrown away
+      const cancel =
+        typeof SharedArrayBuffer === "function" && crossOriginIsolated
+          ? new Int32Array(new SharedArrayBuffer(4))
+          : undefined;
+      const onProgress = extra?.onProgress;
 
       this.queueJob(
         {
           id: jobId,
           kind: "optimise",
           request: options,
+          cancel,
         },
         (data: unknown) => resolve(data as OptimiseApiResponse),
         reject,
-        extra?.abort ?? new AbortController().signal
+        extra?.abort ?? new AbortController().signal,
+        onProgress && ((data) => onProgress(data as OptimiseApiProgress))
       );
     });
   }
@@ -1,11 +1,18 @@
 import init, {
   setup,
   query,
+  query_diverse,
+  query_pareto,
   items_in_slot,
   get_spells,
   get_all_items,
 } from "@/pkg/wasm";
-import { OptimisationConfig } from "./optimiser";
+import {
+  OptimisationConfig,
+  OptimisationDiverseRequest,
+  OptimisationParetoRequest,
+  OptimiseApiProgress,
+} from "./optimiser";
 
 interface WorkerQueryId {
   id: string;
@@ -14,6 +21,18 @@ interface WorkerQueryId {
 interface WorkerQueryOptimise {
   kind: "optimise";
   request: OptimisationConfig;
+  // shared with the page when it can be, which sets it to 1 to cancel the optimisation
+  cancel?: Int32Array;
+}
+
+interface WorkerQueryOptimiseDiverse {
+  kind: "optimise-diverse";
+  request: OptimisationDiverseRequest;
+}
+
+interface WorkerQueryOptimisePareto {
+  kind: "optimise-pareto";
+  request: OptimisationParetoRequest;
 }
 
 interface WorkerQueryGetSlot {
@@ -33,6 +52,8 @@ export type WorkerQuery = WorkerQueryId &
   (
     | WorkerQueryGetSlot
     | WorkerQueryOptimise
+    | WorkerQueryOptimiseDiverse
+    | WorkerQueryOptimisePareto
     | WorkerSpellsGet
     | WorkerGetAllItems
   );
@@ -46,8 +67,30 @@ onmessage = async (message: MessageEvent<WorkerQuery>) => {
   await initialised;
 
   if (message.data.kind === "optimise") {
+    const { id, cancel } = message.data;
+    try {
+      // the optimisation stops early, with the best set so far, once it has been cancelled
+      const response = query(
+        message.data.request,
+        (progress: OptimiseApiProgress) => {
+          postMessage({ id, progress });
+          return cancel === undefined || Atomics.load(cancel, 0) === 0;
+        }
+      );
+      postMessage({ id: message.data.id, success: true, response });
+    } catch (e) {
+      postMessage({ id: message.data.id, success: false, response: e });
+    }
+  } else if (message.data.kind === "optimise-diverse") {
+    try {
+      const response = query_diverse(message.data.request);
+      postMessage({ id: message.data.id, success: true, response });
+    } catch (e) {
+      postMessage({ id: message.data.id, success: false, response: e });
+    }
+  } else if (message.data.kind === "optimise-pareto") {
     try {
-      const response = query(message.data.request);
+      const response = query_pareto(message.data.request);
       postMessage({ id: message.data.id, success: true, response });
     } catch (e) {
       postMessage({ id: message.data.id, success: false, response: e });
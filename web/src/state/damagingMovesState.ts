@@ -1,6 +1,6 @@
 import {
-  SpellDamage,
   OptimisationDamagingMove,
+  SpellEffect,
   SpellSpell,
 } from "@/services/dofus/optimiser";
 import { maxLevelState } from "@/state/state";
@@ -9,17 +9,6 @@ import { atom, PrimitiveAtom } from "jotai";
 export const damagingMovesAtomAtom = atom<
   PrimitiveAtom<OptimiseDamagingMoveString>[]
 >([]);
-function damageToArray(damage: SpellDamage | null): number[] {
-  if (!damage) return Array(5).fill(0);
-
-  return [
-    damage.neutral,
-    damage.air,
-    damage.water,
-    damage.earth,
-    damage.fire,
-  ].map((x) => (x.min + x.max) / 2);
-}
 
 export const damagingMoves = atom<OptimisationDamagingMove[]>((get) => {
   const level = get(maxLevelState);
@@ -27,22 +16,30 @@ export const damagingMoves = atom<OptimisationDamagingMove[]>((get) => {
   return get(damagingMovesAtomAtom)
     .map(get)
     .flatMap((x) => {
-      const effect = x.spell?.effects.findLast((x) => level >= x.level);
+      // the optimiser uses the highest level of the spell the character has unlocked
+      const effect = x.spell?.effects.reduce<SpellEffect | undefined>(
+        (highest, effect) =>
+          effect.level <= level && effect.level > (highest?.level ?? 0)
+            ? effect
+            : highest,
+        undefined
+      );
 
-      if (!effect) return [];
+      if (!x.spell || !x.className || !effect?.normal) return [];
 
       return [
         {
+          source: "spell",
           weight: x.weight,
-          baseDamage: damageToArray(effect.normal),
-          baseCritDamage: damageToArray(effect.critical),
-          baseCritPercent: effect.base_crit ?? 0,
-          critModifyable: effect.base_crit !== null,
+          class: x.className,
+          spell: x.spell.name,
+          level,
         },
       ];
     });
 });
 export interface OptimiseDamagingMoveString {
   weight: number;
+  className: string | null;
   spell: SpellSpell | null;
 }
@@ -198,19 +198,26 @@ export function useDispatchOptimise() {
         while (optimiseRequests.length < numberOfThreads)
           optimiseRequests.push(
             (async () => {
+              const keepBest = (result: OptimiseApiResponse) =>
+                setOptimiseResponse((current) => {
+                  if (!current) return result;
+                  if (current.energy < result.energy) return result;
+                  return current;
+                });
+
               while (!abort.signal.aborted) {
                 const result = await optimiser.optimise(config, {
                   abort: abort.signal,
+                  // show good sets as soon as they're found rather than when a run ends
+                  onProgress: (progress) => {
+                    if (progress.best.valid) keepBest(progress.best);
+                  },
                 });
                 setProgress((current) => ({
                   dispatched: current.dispatched + 1,
                   current: current.current + 1,
                 }));
-                setOptimiseResponse((current) => {
-                  if (!current) return result;
-                  if (current.energy < result.energy) return result;
-                  return current;
-                });
+                keepBest(result);
               }
             })()
           );